@@ -0,0 +1,50 @@
+//! I/O-free coroutine to append contents to a filesystem file.
+
+use std::path::PathBuf;
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to append contents to a filesystem file,
+/// creating it if it does not exist.
+#[derive(Debug)]
+pub struct AppendFile {
+    contents: Option<(PathBuf, Vec<u8>)>,
+}
+
+impl AppendFile {
+    /// Creates a new coroutine from the given file path and contents.
+    pub fn new(path: impl Into<PathBuf>, contents: impl IntoIterator<Item = u8>) -> Self {
+        let contents = contents.into_iter().collect();
+        let contents = Some((path.into(), contents));
+        Self { contents }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        let Some(arg) = arg else {
+            let Some((path, contents)) = self.contents.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to append to file at {}", path.display());
+            return FsResult::Io(FsIo::AppendFile(Err((path, contents))));
+        };
+
+        debug!("resume after appending to file");
+
+        let FsIo::AppendFile(io) = arg else {
+            let err = FsError::unexpected("append file output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(()) => FsResult::Ok(()),
+            Err(path) => FsResult::Io(FsIo::AppendFile(Err(path))),
+        }
+    }
+}
@@ -36,7 +36,7 @@ impl CreateDir {
         debug!("resume after creating directory");
 
         let FsIo::CreateDir(io) = arg else {
-            let err = FsError::InvalidArgument("create dir output", arg);
+            let err = FsError::unexpected("create dir output", arg);
             return FsResult::Err(err);
         };
 
@@ -1,6 +1,6 @@
 //! I/O-free coroutine to create multiple filesystem directories.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{collections::BTreeSet, path::PathBuf};
 
 use log::{debug, trace};
 
@@ -12,7 +12,7 @@ use crate::{
 /// I/O-free coroutine to create multiple filesystem directories.
 #[derive(Debug)]
 pub struct CreateDirs {
-    paths: Option<HashSet<PathBuf>>,
+    paths: Option<BTreeSet<PathBuf>>,
 }
 
 impl CreateDirs {
@@ -36,7 +36,7 @@ impl CreateDirs {
         debug!("resume after creating directories");
 
         let FsIo::CreateDirs(io) = arg else {
-            let err = FsError::InvalidArgument("create dirs output", arg);
+            let err = FsError::unexpected("create dirs output", arg);
             return FsResult::Err(err);
         };
 
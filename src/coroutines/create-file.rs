@@ -5,14 +5,20 @@ use std::path::PathBuf;
 use log::{debug, trace};
 
 use crate::{
+    coroutines::sync_paths::SyncPaths,
     error::{FsError, FsResult},
     io::FsIo,
 };
 
 /// I/O-free coroutine to create a filesystem file.
+///
+/// The file is not flushed to disk by default, see
+/// [`CreateFile::with_durable`].
 #[derive(Debug)]
 pub struct CreateFile {
     contents: Option<(PathBuf, Vec<u8>)>,
+    durable: Option<PathBuf>,
+    sync: Option<SyncPaths>,
 }
 
 impl CreateFile {
@@ -20,11 +26,30 @@ impl CreateFile {
     pub fn new(path: impl Into<PathBuf>, contents: impl IntoIterator<Item = u8>) -> Self {
         let contents = contents.into_iter().collect();
         let contents = Some((path.into(), contents));
-        Self { contents }
+
+        Self {
+            contents,
+            durable: None,
+            sync: None,
+        }
+    }
+
+    /// Flushes the file then its parent directory to disk once
+    /// created, using [`SyncPaths`].
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = match &self.contents {
+            Some((path, _)) if durable => Some(path.clone()),
+            _ => None,
+        };
+        self
     }
 
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        if let Some(sync) = &mut self.sync {
+            return sync.resume(arg);
+        }
+
         let Some(arg) = arg else {
             let Some((path, contents)) = self.contents.take() else {
                 return FsResult::Err(FsError::MissingInput);
@@ -37,12 +62,15 @@ impl CreateFile {
         debug!("resume after creating file");
 
         let FsIo::CreateFile(io) = arg else {
-            let err = FsError::InvalidArgument("create file output", arg);
+            let err = FsError::unexpected("create file output", arg);
             return FsResult::Err(err);
         };
 
         match io {
-            Ok(()) => FsResult::Ok(()),
+            Ok(()) => match self.durable.take() {
+                Some(path) => self.sync.insert(SyncPaths::files([path])).resume(None),
+                None => FsResult::Ok(()),
+            },
             Err(path) => FsResult::Io(FsIo::CreateFile(Err(path))),
         }
     }
@@ -1,18 +1,24 @@
 //! I/O-free coroutine to create multiple filesystem files.
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::BTreeMap, path::PathBuf};
 
 use log::{debug, trace};
 
 use crate::{
+    coroutines::sync_paths::SyncPaths,
     error::{FsError, FsResult},
     io::FsIo,
 };
 
 /// I/O-free coroutine to create multiple filesystem files.
+///
+/// Files are not flushed to disk by default, see
+/// [`CreateFiles::with_durable`].
 #[derive(Debug)]
 pub struct CreateFiles {
-    contents: Option<HashMap<PathBuf, Vec<u8>>>,
+    contents: Option<BTreeMap<PathBuf, Vec<u8>>>,
+    durable: Option<Vec<PathBuf>>,
+    sync: Option<SyncPaths>,
 }
 
 impl CreateFiles {
@@ -24,11 +30,30 @@ impl CreateFiles {
             .into_iter()
             .map(|(path, contents)| (path.into(), contents.into_iter().collect()));
         let contents = Some(contents.collect());
-        Self { contents }
+
+        Self {
+            contents,
+            durable: None,
+            sync: None,
+        }
+    }
+
+    /// Flushes the files then their parent directories to disk once
+    /// created, using [`SyncPaths`].
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = match &self.contents {
+            Some(contents) if durable => Some(contents.keys().cloned().collect()),
+            _ => None,
+        };
+        self
     }
 
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        if let Some(sync) = &mut self.sync {
+            return sync.resume(arg);
+        }
+
         let Some(arg) = arg else {
             let Some(contents) = self.contents.take() else {
                 return FsResult::Err(FsError::MissingInput);
@@ -41,12 +66,15 @@ impl CreateFiles {
         debug!("resume after creating files");
 
         let FsIo::CreateFiles(io) = arg else {
-            let err = FsError::InvalidArgument("create files output", arg);
+            let err = FsError::unexpected("create files output", arg);
             return FsResult::Err(err);
         };
 
         match io {
-            Ok(()) => FsResult::Ok(()),
+            Ok(()) => match self.durable.take() {
+                Some(paths) => self.sync.insert(SyncPaths::files(paths)).resume(None),
+                None => FsResult::Ok(()),
+            },
             Err(path) => FsResult::Io(FsIo::CreateFiles(Err(path))),
         }
     }
@@ -0,0 +1,78 @@
+//! I/O-free coroutine to create a new filesystem file.
+
+use std::path::PathBuf;
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::sync_paths::SyncPaths,
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to create a new filesystem file, failing if it
+/// already exists.
+///
+/// The file is not flushed to disk by default, see
+/// [`CreateNewFile::with_durable`].
+#[derive(Debug)]
+pub struct CreateNewFile {
+    contents: Option<(PathBuf, Vec<u8>)>,
+    durable: Option<PathBuf>,
+    sync: Option<SyncPaths>,
+}
+
+impl CreateNewFile {
+    /// Creates a new coroutine from the given file path and contents.
+    pub fn new(path: impl Into<PathBuf>, contents: impl IntoIterator<Item = u8>) -> Self {
+        let contents = contents.into_iter().collect();
+        let contents = Some((path.into(), contents));
+
+        Self {
+            contents,
+            durable: None,
+            sync: None,
+        }
+    }
+
+    /// Flushes the file then its parent directory to disk once
+    /// created, using [`SyncPaths`].
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = match &self.contents {
+            Some((path, _)) if durable => Some(path.clone()),
+            _ => None,
+        };
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        if let Some(sync) = &mut self.sync {
+            return sync.resume(arg);
+        }
+
+        let Some(arg) = arg else {
+            let Some((path, contents)) = self.contents.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to create new file at {}", path.display());
+            return FsResult::Io(FsIo::CreateNewFile(Err((path, contents))));
+        };
+
+        debug!("resume after creating new file");
+
+        let FsIo::CreateNewFile(io) = arg else {
+            let err = FsError::unexpected("create new file output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(()) => match self.durable.take() {
+                Some(path) => self.sync.insert(SyncPaths::files([path])).resume(None),
+                None => FsResult::Ok(()),
+            },
+            Err(path) => FsResult::Io(FsIo::CreateNewFile(Err(path))),
+        }
+    }
+}
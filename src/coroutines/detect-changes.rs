@@ -0,0 +1,92 @@
+//! I/O-free coroutine to detect changes under a filesystem directory
+//! since a previous snapshot.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    coroutines::{
+        diff_trees::{diff, relativize, Compare, TreeDiff},
+        walk::Walk,
+    },
+    error::FsResult,
+    io::{FsIo, FsMetadata},
+};
+
+/// The state of a filesystem directory tree at a given time.
+///
+/// Snapshots can be serialized, so that changes can be detected
+/// across process restarts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, FsMetadata>,
+}
+
+impl Snapshot {
+    /// Creates an empty snapshot of the given root directory.
+    ///
+    /// Detecting changes from an empty snapshot reports all entries
+    /// as added.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the root directory path.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the metadata of all entries, by path relative to the
+    /// root directory.
+    pub fn entries(&self) -> &BTreeMap<PathBuf, FsMetadata> {
+        &self.entries
+    }
+}
+
+/// I/O-free coroutine to detect changes under a filesystem directory
+/// since a previous snapshot.
+///
+/// The directory is walked using [`Walk`], then compared to the
+/// snapshot by size and modification time.
+///
+/// Returns the changes, alongside the new snapshot to use for the
+/// next detection.
+#[derive(Debug)]
+pub struct DetectChanges {
+    snapshot: Snapshot,
+    walk: Walk,
+}
+
+impl DetectChanges {
+    /// Creates a new coroutine from the given previous snapshot.
+    pub fn new(snapshot: Snapshot) -> Self {
+        let walk = Walk::new(&snapshot.root);
+        Self { snapshot, walk }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<(TreeDiff, Snapshot)> {
+        let entries = match self.walk.resume(arg) {
+            FsResult::Ok(entries) => entries,
+            FsResult::Err(err) => return FsResult::Err(err),
+            FsResult::Io(io) => return FsResult::Io(io),
+        };
+
+        let root = self.snapshot.root.clone();
+        let entries = relativize(&root, entries);
+        let old = mem::replace(&mut self.snapshot.entries, entries);
+        let new = &self.snapshot.entries;
+
+        let diff = diff(&old, new, Compare::SizeAndModified, &BTreeSet::new());
+
+        FsResult::Ok((diff, self.snapshot.clone()))
+    }
+}
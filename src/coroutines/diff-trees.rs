@@ -0,0 +1,529 @@
+//! I/O-free coroutine to diff two filesystem directory trees.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::walk::Walk,
+    error::{FsError, FsResult},
+    io::{FsChunk, FsIo, FsKind, FsMetadata},
+};
+
+/// The default maximum number of bytes read by a single request when
+/// comparing files by [`Compare::Content`].
+pub const DEFAULT_BATCH_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The strategy used to compare two files sharing the same size.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compare {
+    /// Files are considered equal when they share the same size and
+    /// last modification time.
+    #[default]
+    SizeAndModified,
+
+    /// Files are considered equal when they share the same contents.
+    ///
+    /// Files that may be equal need to be read.
+    Content,
+}
+
+/// The differences between two filesystem directory trees.
+///
+/// All paths are relative to the root directories.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TreeDiff {
+    /// Entries only present in the new tree.
+    pub added: BTreeSet<PathBuf>,
+
+    /// Entries only present in the old tree.
+    pub removed: BTreeSet<PathBuf>,
+
+    /// Entries present in both trees, with different kinds or
+    /// contents.
+    pub modified: BTreeSet<PathBuf>,
+
+    /// Files only present in the old tree, matching a single file
+    /// only present in the new tree, by old path.
+    pub moved: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl TreeDiff {
+    /// Returns `true` if both trees are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    WalkOld(Walk),
+    WalkNew(Walk),
+    CompareContents(CompareContents),
+}
+
+/// I/O-free coroutine to diff two filesystem directory trees.
+///
+/// Both trees are walked using [`Walk`], then entries are compared
+/// by relative path. Directories are never considered modified,
+/// their entries are compared instead. Moves are only detected for
+/// files: a removed file is considered moved when a single added
+/// file compares equal to it.
+///
+/// When comparing by [`Compare::Content`], files sharing the same
+/// size are read chunk by chunk using [`FsIo::ReadChunks`] requests of
+/// bounded size (see [`DiffTrees::with_batch_size`]), and split into
+/// groups sharing the same contents so far. Each file is read at most
+/// once, and stops being read as soon as no file of the other tree
+/// shares its contents. Chunks are dropped once compared.
+#[derive(Debug)]
+pub struct DiffTrees {
+    old_root: PathBuf,
+    new_root: PathBuf,
+    compare: Compare,
+    batch_size: u64,
+    old: BTreeMap<PathBuf, FsMetadata>,
+    new: BTreeMap<PathBuf, FsMetadata>,
+    state: State,
+}
+
+impl DiffTrees {
+    /// Creates a new coroutine from the given old and new root
+    /// directory paths.
+    pub fn new(old: impl Into<PathBuf>, new: impl Into<PathBuf>) -> Self {
+        let old_root = old.into();
+        let new_root = new.into();
+        let state = State::WalkOld(Walk::new(&old_root));
+
+        Self {
+            old_root,
+            new_root,
+            compare: Compare::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            old: BTreeMap::new(),
+            new: BTreeMap::new(),
+            state,
+        }
+    }
+
+    /// Sets the strategy used to compare files.
+    pub fn with_compare(mut self, compare: Compare) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Sets the maximum number of bytes read by a single request when
+    /// comparing files by [`Compare::Content`].
+    ///
+    /// Files of a same group are always read together, by chunks of
+    /// at least one byte.
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<TreeDiff> {
+        loop {
+            match &mut self.state {
+                State::WalkOld(coroutine) => {
+                    let entries = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(entries) => entries,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    self.old = relativize(&self.old_root, entries);
+                    self.state = State::WalkNew(Walk::new(&self.new_root));
+                }
+                State::WalkNew(coroutine) => {
+                    let entries = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(entries) => entries,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    self.new = relativize(&self.new_root, entries);
+
+                    let candidates = match self.compare {
+                        Compare::SizeAndModified => BTreeMap::new(),
+                        Compare::Content => candidates(&self.old, &self.new),
+                    };
+
+                    if candidates.is_empty() {
+                        return FsResult::Ok(self.diff(&BTreeSet::new()));
+                    }
+
+                    debug!("compare contents of {} groups of files", candidates.len());
+                    let roots = (self.old_root.clone(), self.new_root.clone());
+                    let coroutine = CompareContents::new(roots, candidates, self.batch_size);
+                    self.state = State::CompareContents(coroutine);
+                }
+                State::CompareContents(coroutine) => {
+                    let equal = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(equal) => equal,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    return FsResult::Ok(self.diff(&equal));
+                }
+            }
+        }
+    }
+
+    fn diff(&mut self, equal: &BTreeSet<(PathBuf, PathBuf)>) -> TreeDiff {
+        let old = mem::take(&mut self.old);
+        let new = mem::take(&mut self.new);
+        diff(&old, &new, self.compare, equal)
+    }
+}
+
+/// Files that may share the same contents, by size: files of the
+/// old tree, then files of the new tree, by relative path.
+pub(crate) type Candidates = BTreeMap<u64, (BTreeSet<PathBuf>, BTreeSet<PathBuf>)>;
+
+/// Returns the files that need their contents to be compared,
+/// grouped by size: files present in both trees with the same size,
+/// and files only present in one tree.
+///
+/// Groups without files from both trees are left out.
+pub(crate) fn candidates(
+    old: &BTreeMap<PathBuf, FsMetadata>,
+    new: &BTreeMap<PathBuf, FsMetadata>,
+) -> Candidates {
+    let mut candidates = Candidates::new();
+
+    for (path, metadata) in files(old) {
+        match new.get(path) {
+            Some(m) if m.kind == FsKind::File && m.len == metadata.len => {
+                let (old, new) = candidates.entry(metadata.len).or_default();
+                old.insert(path.clone());
+                new.insert(path.clone());
+            }
+            Some(_) => (),
+            None => {
+                let (old, _) = candidates.entry(metadata.len).or_default();
+                old.insert(path.clone());
+            }
+        }
+    }
+
+    for (path, metadata) in files(new).filter(|(path, _)| !old.contains_key(*path)) {
+        let (_, new) = candidates.entry(metadata.len).or_default();
+        new.insert(path.clone());
+    }
+
+    candidates.retain(|_, (old, new)| !old.is_empty() && !new.is_empty());
+    candidates
+}
+
+/// A group of files of the same size, sharing the same contents up to
+/// the given offset.
+#[derive(Debug)]
+struct Group {
+    len: u64,
+    offset: u64,
+    old: BTreeSet<PathBuf>,
+    new: BTreeSet<PathBuf>,
+}
+
+/// I/O-free coroutine to compare contents of files, by chunks of
+/// bounded size.
+///
+/// Groups of files are read chunk by chunk, then split by chunk
+/// contents. Groups left without files from both trees are not read
+/// anymore.
+///
+/// Returns the pairs of files sharing the same contents, by relative
+/// path.
+#[derive(Debug)]
+pub(crate) struct CompareContents {
+    old_root: PathBuf,
+    new_root: PathBuf,
+    batch_size: u64,
+    queue: VecDeque<Group>,
+    batch: Vec<(Group, u64)>,
+    equal: BTreeSet<(PathBuf, PathBuf)>,
+    wants_io: bool,
+}
+
+impl CompareContents {
+    /// Creates a new coroutine from the given old and new roots, and
+    /// the given candidates.
+    pub(crate) fn new(
+        (old_root, new_root): (PathBuf, PathBuf),
+        candidates: Candidates,
+        batch_size: u64,
+    ) -> Self {
+        let mut coroutine = Self {
+            old_root,
+            new_root,
+            batch_size,
+            queue: VecDeque::new(),
+            batch: Vec::new(),
+            equal: BTreeSet::new(),
+            wants_io: false,
+        };
+
+        for (len, (old, new)) in candidates {
+            let group = Group {
+                len,
+                offset: 0,
+                old,
+                new,
+            };
+
+            // empty files do not need to be read
+            if len == 0 {
+                coroutine.insert_equal(group);
+            } else {
+                coroutine.queue.push_back(group);
+            }
+        }
+
+        coroutine
+    }
+
+    /// Makes the coroutine progress.
+    pub(crate) fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeSet<(PathBuf, PathBuf)>> {
+        match arg {
+            None if self.wants_io => return FsResult::Err(FsError::MissingInput),
+            None => (),
+            Some(arg) => {
+                debug!("resume after reading chunks");
+
+                let FsIo::ReadChunks(io) = arg else {
+                    let err = FsError::unexpected("read chunks output", arg);
+                    return FsResult::Err(err);
+                };
+
+                let chunks = match io {
+                    Ok(chunks) => chunks,
+                    Err(input) => return FsResult::Io(FsIo::ReadChunks(Err(input))),
+                };
+
+                for (group, len) in mem::take(&mut self.batch) {
+                    self.split(group, len, &chunks);
+                }
+            }
+        }
+
+        let mut size = 0;
+
+        while let Some(group) = self.queue.front() {
+            let files = (group.old.len() + group.new.len()) as u64;
+            let len = (self.batch_size / files).clamp(1, group.len - group.offset);
+
+            if !self.batch.is_empty() && size + files * len > self.batch_size {
+                break;
+            }
+
+            let Some(group) = self.queue.pop_front() else {
+                break;
+            };
+
+            size += files * len;
+            self.batch.push((group, len));
+        }
+
+        if self.batch.is_empty() {
+            self.wants_io = false;
+            return FsResult::Ok(mem::take(&mut self.equal));
+        }
+
+        let mut chunks = BTreeMap::new();
+
+        for (group, len) in &self.batch {
+            let chunk = FsChunk {
+                offset: group.offset,
+                len: *len,
+            };
+
+            for path in &group.old {
+                chunks.insert(self.old_root.join(path), chunk);
+            }
+
+            for path in &group.new {
+                chunks.insert(self.new_root.join(path), chunk);
+            }
+        }
+
+        trace!("wants I/O to read chunks of {} files", chunks.len());
+
+        self.wants_io = true;
+        FsResult::Io(FsIo::ReadChunks(Err(chunks)))
+    }
+
+    /// Splits the given group by contents of the chunks of the given
+    /// length.
+    ///
+    /// Files with a short chunk changed since they were walked, they
+    /// are left out.
+    fn split(&mut self, group: Group, len: u64, chunks: &BTreeMap<PathBuf, Vec<u8>>) {
+        let mut groups: BTreeMap<&[u8], Group> = BTreeMap::new();
+        let offset = group.offset + len;
+
+        let old = group.old.into_iter().map(|path| (false, path));
+        let new = group.new.into_iter().map(|path| (true, path));
+
+        for (is_new, path) in old.chain(new) {
+            let root = if is_new {
+                &self.new_root
+            } else {
+                &self.old_root
+            };
+
+            let Some(chunk) = chunks.get(&root.join(&path)) else {
+                continue;
+            };
+
+            if chunk.len() as u64 != len {
+                continue;
+            }
+
+            let group = groups.entry(chunk).or_insert_with(|| Group {
+                len: group.len,
+                offset,
+                old: BTreeSet::new(),
+                new: BTreeSet::new(),
+            });
+
+            if is_new {
+                group.new.insert(path);
+            } else {
+                group.old.insert(path);
+            }
+        }
+
+        for group in groups.into_values() {
+            if group.old.is_empty() || group.new.is_empty() {
+                continue;
+            }
+
+            if group.offset == group.len {
+                self.insert_equal(group);
+            } else {
+                self.queue.push_back(group);
+            }
+        }
+    }
+
+    fn insert_equal(&mut self, group: Group) {
+        for old in &group.old {
+            for new in &group.new {
+                self.equal.insert((old.clone(), new.clone()));
+            }
+        }
+    }
+}
+
+/// Diffs the given trees entries.
+///
+/// When comparing by [`Compare::Content`], the given pairs of
+/// relative paths must contain the pairs of [candidates] sharing the
+/// same contents.
+pub(crate) fn diff(
+    old: &BTreeMap<PathBuf, FsMetadata>,
+    new: &BTreeMap<PathBuf, FsMetadata>,
+    compare: Compare,
+    equal: &BTreeSet<(PathBuf, PathBuf)>,
+) -> TreeDiff {
+    let mut diff = TreeDiff::default();
+
+    let equal = |old_path: &Path, old: &FsMetadata, new_path: &Path, new: &FsMetadata| {
+        if old.kind != new.kind {
+            return false;
+        }
+
+        match old.kind {
+            FsKind::Dir => true,
+            FsKind::File if compare == Compare::Content => {
+                let pair = (old_path.to_path_buf(), new_path.to_path_buf());
+                old.len == new.len && equal.contains(&pair)
+            }
+            _ => old.len == new.len && old.modified == new.modified,
+        }
+    };
+
+    for (path, old_metadata) in old {
+        match new.get(path) {
+            None => {
+                diff.removed.insert(path.clone());
+            }
+            Some(new_metadata) => {
+                if !equal(path, old_metadata, path, new_metadata) {
+                    diff.modified.insert(path.clone());
+                }
+            }
+        }
+    }
+
+    diff.added = new
+        .keys()
+        .filter(|path| !old.contains_key(*path))
+        .cloned()
+        .collect();
+
+    for from in mem::take(&mut diff.removed) {
+        let from_metadata = &old[&from];
+
+        let mut candidates = diff.added.iter().filter(|to| {
+            let to_metadata = &new[*to];
+            // without modification time, only contents can tell
+            // whether files are equal
+            let comparable = compare == Compare::Content || to_metadata.modified.is_some();
+
+            from_metadata.kind == FsKind::File
+                && comparable
+                && equal(&from, from_metadata, to, to_metadata)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(to), None) => {
+                let to = to.clone();
+                diff.added.remove(&to);
+                diff.moved.insert(from, to);
+            }
+            _ => {
+                diff.removed.insert(from);
+            }
+        }
+    }
+
+    debug!(
+        "found {} added, {} removed, {} modified and {} moved entries",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.moved.len(),
+    );
+
+    diff
+}
+
+fn files(entries: &BTreeMap<PathBuf, FsMetadata>) -> impl Iterator<Item = (&PathBuf, &FsMetadata)> {
+    entries.iter().filter(|(_, m)| m.kind == FsKind::File)
+}
+
+pub(crate) fn relativize(
+    root: &Path,
+    entries: BTreeMap<PathBuf, FsMetadata>,
+) -> BTreeMap<PathBuf, FsMetadata> {
+    entries
+        .into_iter()
+        .map(|(path, metadata)| match path.strip_prefix(root) {
+            Ok(relative) => (relative.to_path_buf(), metadata),
+            Err(_) => (path, metadata),
+        })
+        .collect()
+}
@@ -0,0 +1,117 @@
+//! I/O-free coroutine to compute the disk usage of a filesystem
+//! directory tree.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use log::debug;
+
+use crate::{
+    coroutines::walk::Walk,
+    error::FsResult,
+    io::{FsIo, FsKind},
+};
+
+/// The disk usage of a filesystem directory, including all its
+/// subdirectories.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Usage {
+    /// The total size of files, in bytes.
+    ///
+    /// This is the apparent size of files, as reported by their
+    /// metadata, not the number of blocks allocated on disk.
+    /// Directories themselves do not count.
+    pub size: u64,
+
+    /// The number of files, including symbolic links and other
+    /// non-directory entries.
+    pub files: u64,
+
+    /// The number of subdirectories.
+    pub dirs: u64,
+}
+
+/// I/O-free coroutine to compute the disk usage of a filesystem
+/// directory tree.
+///
+/// The tree is walked using [`Walk`], so symbolic links are not
+/// followed.
+///
+/// Returns the usage of the root directory and of each directory
+/// below it, by path.
+#[derive(Debug)]
+pub struct DiskUsage {
+    root: PathBuf,
+    hard_links_once: bool,
+    walk: Walk,
+}
+
+impl DiskUsage {
+    /// Creates a new coroutine from the given root directory path.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let walk = Walk::new(&root);
+
+        Self {
+            root,
+            hard_links_once: false,
+            walk,
+        }
+    }
+
+    /// Counts files with multiple hard links once, like `du` does.
+    ///
+    /// Files are identified by their device and inode numbers, which
+    /// are only available on Unix. Only the first path found, in
+    /// path order, is counted.
+    pub fn with_hard_links_once(mut self, hard_links_once: bool) -> Self {
+        self.hard_links_once = hard_links_once;
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, Usage>> {
+        let entries = match self.walk.resume(arg) {
+            FsResult::Ok(entries) => entries,
+            FsResult::Err(err) => return FsResult::Err(err),
+            FsResult::Io(io) => return FsResult::Io(io),
+        };
+
+        let mut usages = BTreeMap::new();
+        usages.insert(self.root.clone(), Usage::default());
+
+        let mut inodes = BTreeSet::new();
+
+        for (path, metadata) in entries {
+            let is_dir = metadata.kind == FsKind::Dir;
+
+            if is_dir {
+                usages.entry(path.clone()).or_default();
+            } else if self.hard_links_once
+                && metadata.nlink > 1
+                && !inodes.insert((metadata.dev, metadata.ino))
+            {
+                debug!("skip hard link {}", path.display());
+                continue;
+            }
+
+            let parents = path.ancestors().skip(1);
+
+            for parent in parents.take_while(|parent| parent.starts_with(&self.root)) {
+                let usage: &mut Usage = usages.entry(parent.to_path_buf()).or_default();
+
+                if is_dir {
+                    usage.dirs += 1;
+                } else {
+                    usage.files += 1;
+                    usage.size += metadata.len;
+                }
+            }
+        }
+
+        FsResult::Ok(usages)
+    }
+}
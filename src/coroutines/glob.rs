@@ -0,0 +1,682 @@
+//! I/O-free coroutine to find filesystem entries matching glob
+//! patterns.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io, mem,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::ignore::IgnoreRules,
+    error::{FsError, FsIoError, FsResult},
+    io::{FsIo, FsKind},
+};
+
+/// A token of a glob pattern segment.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Token {
+    /// A literal character.
+    Char(char),
+
+    /// Any single character (`?`).
+    Any,
+
+    /// Any sequence of characters, including an empty one (`*`).
+    Star,
+
+    /// A character class (`[a-z]`, `[!abc]` etc), made of
+    /// inclusive ranges.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(x) => *x == c,
+            Self::Any => true,
+            Self::Star => false,
+            Self::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// A segment of a glob pattern, matching one path component, except
+/// for [`Segment::Recursive`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Segment {
+    /// A segment without wildcard, matching itself only.
+    Literal(String),
+
+    /// A segment with wildcards.
+    Wildcard(Vec<Token>),
+
+    /// A `**` segment, matching zero or more path components.
+    Recursive,
+}
+
+impl Segment {
+    /// Parses the given pattern segment.
+    ///
+    /// Backslashes escape the next character. Unclosed character
+    /// classes are matched literally.
+    pub(crate) fn parse(segment: &str) -> Self {
+        if segment == "**" {
+            return Self::Recursive;
+        }
+
+        let chars: Vec<char> = segment.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Char(chars[i + 1]));
+                    i += 2;
+                    continue;
+                }
+                '?' => tokens.push(Token::Any),
+                '*' if tokens.last() == Some(&Token::Star) => (),
+                '*' => tokens.push(Token::Star),
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        tokens.push(token);
+                        i += len + 1;
+                        continue;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                },
+                c => tokens.push(Token::Char(c)),
+            }
+
+            i += 1;
+        }
+
+        let literal = tokens.iter().all(|token| matches!(token, Token::Char(_)));
+
+        if !literal {
+            return Self::Wildcard(tokens);
+        }
+
+        let literal = tokens.into_iter().map(|token| match token {
+            Token::Char(c) => c,
+            _ => unreachable!(),
+        });
+
+        Self::Literal(literal.collect())
+    }
+
+    /// Returns `true` if the given path component name matches the
+    /// segment. A [`Segment::Recursive`] matches any name.
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Literal(literal) => literal == name,
+            Self::Wildcard(tokens) => {
+                let name: Vec<char> = name.chars().collect();
+                match_tokens(tokens, &name)
+            }
+            Self::Recursive => true,
+        }
+    }
+
+    /// Returns `true` if the segment explicitly matches hidden names,
+    /// by starting with a literal dot.
+    fn matches_hidden(&self) -> bool {
+        match self {
+            Self::Literal(literal) => literal.starts_with('.'),
+            Self::Wildcard(tokens) => tokens.first() == Some(&Token::Char('.')),
+            Self::Recursive => false,
+        }
+    }
+}
+
+/// Parses a character class, starting right after the opening
+/// bracket.
+///
+/// Returns the class token and the number of characters consumed,
+/// including the closing bracket.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+
+    loop {
+        let c = *chars.get(i)?;
+
+        // a closing bracket right after the opening one is literal
+        if c == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                ranges.push((c, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Matches the given name against the given tokens, backtracking on
+/// the last star.
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    let (mut t, mut n) = (0, 0);
+    let mut star = None;
+
+    while n < name.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                star = Some((t, n));
+                t += 1;
+                continue;
+            }
+            Some(token) if token.matches(name[n]) => {
+                t += 1;
+                n += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        match star {
+            Some((star_t, star_n)) => {
+                t = star_t + 1;
+                n = star_n + 1;
+                star = Some((star_t, star_n + 1));
+            }
+            None => return false,
+        }
+    }
+
+    tokens[t..].iter().all(|token| *token == Token::Star)
+}
+
+/// Expands braces of the given pattern, for example `*.{vcf,ics}`
+/// into `*.vcf` and `*.ics`.
+///
+/// Braces can be nested. Braces without comma or without closing
+/// brace are kept literally.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '{' => {
+                if let Some((alternatives, end)) = split_braces(&chars, i) {
+                    let prefix: String = chars[..i].iter().collect();
+                    let suffix: String = chars[end + 1..].iter().collect();
+
+                    return alternatives
+                        .into_iter()
+                        .flat_map(|alt| expand_braces(&format!("{prefix}{alt}{suffix}")))
+                        .collect();
+                }
+
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    vec![pattern.to_owned()]
+}
+
+/// Splits the top-level alternatives of the braces opened at the
+/// given index.
+///
+/// Returns the alternatives and the index of the closing brace.
+fn split_braces(chars: &[char], open: usize) -> Option<(Vec<String>, usize)> {
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut alternative = String::new();
+    let mut i = open + 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                alternative.extend(chars.get(i..i + 2).unwrap_or(&chars[i..]));
+                i += 2;
+                continue;
+            }
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' if alternatives.is_empty() => return None,
+            '}' => {
+                alternatives.push(alternative);
+                return Some((alternatives, i));
+            }
+            ',' if depth == 0 => {
+                alternatives.push(mem::take(&mut alternative));
+                i += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        alternative.push(chars[i]);
+        i += 1;
+    }
+
+    None
+}
+
+/// Parses the given pattern into segments, without brace expansion.
+///
+/// Empty and `.` components are skipped, so that patterns are always
+/// relative. Consecutive `**` segments are merged.
+pub(crate) fn parse_segments(pattern: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+
+    for segment in pattern.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+
+        let segment = Segment::parse(segment);
+
+        if segment == Segment::Recursive && segments.last() == Some(&Segment::Recursive) {
+            continue;
+        }
+
+        segments.push(segment);
+    }
+
+    segments
+}
+
+/// A directory to match against the segments of a pattern, starting
+/// at the given segment index.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Task {
+    dir: PathBuf,
+    pattern: usize,
+    index: usize,
+}
+
+/// The I/O request awaited by [`Glob`].
+#[derive(Debug)]
+enum Pending {
+    /// Entries of the given directory.
+    Dir(PathBuf),
+
+    /// Kinds of the given directory entries.
+    Listing(PathBuf),
+
+    /// Existence of the given entry.
+    Entry(PathBuf),
+
+    /// Contents of the given ignore file, in the given directory.
+    IgnoreFile(PathBuf, PathBuf),
+}
+
+/// I/O-free coroutine to find filesystem entries matching glob
+/// patterns.
+///
+/// Patterns are relative to the root directory, and support `*`,
+/// `?`, character classes (`[a-z]`, `[!abc]`), brace expansion
+/// (`{vcf,ics}`) and `**` to match any number of directories.
+/// Hidden entries are only matched by segments starting with a dot,
+/// see [`Glob::with_hidden`].
+///
+/// Only directories that can contain matches are read: leading
+/// literal segments like `contacts/` in `contacts/**/*.vcf` are
+/// joined without any I/O. Each directory is read once, using one
+/// [`FsIo::ReadDir`] request followed by one [`FsIo::ReadMetadatas`]
+/// request. Symbolic links are not followed.
+///
+/// Entries can be filtered using gitignore-style rules, see
+/// [`Glob::with_ignore_file`] and [`Glob::with_ignore_rules`].
+///
+/// Missing directories do not match anything, so the loop needs to
+/// send I/O errors back to the coroutine (see [`FsIo::Error`]).
+///
+/// Returns the absolute paths of matching entries.
+#[derive(Debug)]
+pub struct Glob {
+    root: PathBuf,
+    patterns: Vec<Vec<Segment>>,
+    hidden: bool,
+    queue: VecDeque<Task>,
+    seen: BTreeSet<Task>,
+    listings: BTreeMap<PathBuf, BTreeMap<PathBuf, FsKind>>,
+    pending: Option<Pending>,
+    matches: BTreeSet<PathBuf>,
+    ignore_files: Vec<String>,
+    ignore_files_read: BTreeSet<PathBuf>,
+    rules: IgnoreRules,
+}
+
+impl Glob {
+    /// Creates a new coroutine from the given root directory and
+    /// pattern.
+    pub fn new(root: impl Into<PathBuf>, pattern: impl AsRef<str>) -> Self {
+        let glob = Self {
+            root: root.into(),
+            patterns: Vec::new(),
+            hidden: false,
+            queue: VecDeque::new(),
+            seen: BTreeSet::new(),
+            listings: BTreeMap::new(),
+            pending: None,
+            matches: BTreeSet::new(),
+            ignore_files: Vec::new(),
+            ignore_files_read: BTreeSet::new(),
+            rules: IgnoreRules::new(),
+        };
+
+        glob.with_pattern(pattern)
+    }
+
+    /// Adds another pattern, relative to the same root directory.
+    pub fn with_pattern(mut self, pattern: impl AsRef<str>) -> Self {
+        for pattern in expand_braces(pattern.as_ref()) {
+            let segments = parse_segments(&pattern);
+
+            if segments.is_empty() {
+                continue;
+            }
+
+            let task = Task {
+                dir: self.root.clone(),
+                pattern: self.patterns.len(),
+                index: 0,
+            };
+
+            self.patterns.push(segments);
+            self.seen.insert(task.clone());
+            self.queue.push_back(task);
+        }
+
+        self
+    }
+
+    /// Matches hidden entries with wildcards and `**` as well.
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Reads ignore files with the given name (like [`GITIGNORE`])
+    /// in the root directory and every directory below it leading
+    /// to matches, then applies their rules.
+    ///
+    /// [`GITIGNORE`]: crate::coroutines::ignore::GITIGNORE
+    pub fn with_ignore_file(mut self, name: impl Into<String>) -> Self {
+        self.ignore_files.push(name.into());
+        self
+    }
+
+    /// Applies the given rules, before rules of ignore files.
+    pub fn with_ignore_rules(mut self, rules: IgnoreRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeSet<PathBuf>> {
+        match (self.pending.take(), arg) {
+            (None, None) => (),
+            (Some(_), None) => return FsResult::Err(FsError::MissingInput),
+            (Some(Pending::Dir(dir)), Some(FsIo::ReadDir(Ok(paths)))) => {
+                debug!("resume after reading dir {}", dir.display());
+
+                if !paths.is_empty() {
+                    trace!("wants I/O to read metadata of {} entries", paths.len());
+                    self.pending = Some(Pending::Listing(dir));
+                    return FsResult::Io(FsIo::ReadMetadatas(Err(paths)));
+                }
+
+                self.listings.insert(dir, BTreeMap::new());
+            }
+            (Some(Pending::Listing(dir)), Some(FsIo::ReadMetadatas(Ok(metadatas)))) => {
+                debug!("resume after reading metadata of {}", dir.display());
+
+                let listing = metadatas
+                    .into_iter()
+                    .map(|(path, metadata)| (path, metadata.kind))
+                    .collect();
+
+                self.listings.insert(dir, listing);
+            }
+            (Some(Pending::Entry(path)), Some(FsIo::ReadMetadata(Ok(metadata)))) => {
+                if !self.rules.is_ignored(&path, metadata.kind == FsKind::Dir) {
+                    self.matches.insert(path);
+                }
+
+                self.queue.pop_front();
+            }
+            (Some(Pending::IgnoreFile(dir, _)), Some(FsIo::ReadFile(Ok(contents)))) => {
+                debug!("resume after reading ignore file of {}", dir.display());
+                self.rules.add(&dir, &String::from_utf8_lossy(&contents));
+            }
+            (Some(Pending::IgnoreFile(_, path)), Some(FsIo::Error(err))) if is_missing(&err) => {
+                trace!("skip missing ignore file {}", path.display());
+            }
+            (Some(Pending::Dir(dir)), Some(FsIo::Error(err))) if is_missing(&err) => {
+                debug!("skip missing dir {}: {err}", dir.display());
+                self.listings.insert(dir, BTreeMap::new());
+            }
+            (Some(Pending::Entry(_)), Some(FsIo::Error(err))) if is_missing(&err) => {
+                self.queue.pop_front();
+            }
+            (_, Some(FsIo::Error(err))) => return FsResult::Err(FsError::Io(err)),
+            (pending, Some(FsIo::ReadDir(Err(input)))) => {
+                self.pending = pending;
+                return FsResult::Io(FsIo::ReadDir(Err(input)));
+            }
+            (pending, Some(FsIo::ReadMetadatas(Err(input)))) => {
+                self.pending = pending;
+                return FsResult::Io(FsIo::ReadMetadatas(Err(input)));
+            }
+            (pending, Some(FsIo::ReadMetadata(Err(input)))) => {
+                self.pending = pending;
+                return FsResult::Io(FsIo::ReadMetadata(Err(input)));
+            }
+            (pending, Some(FsIo::ReadFile(Err(input)))) => {
+                self.pending = pending;
+                return FsResult::Io(FsIo::ReadFile(Err(input)));
+            }
+            (_, Some(arg)) => {
+                let err = FsError::unexpected("glob output", arg);
+                return FsResult::Err(err);
+            }
+        }
+
+        while let Some(task) = self.queue.front().cloned() {
+            if let Some((pending, io)) = self.step(task) {
+                self.pending = Some(pending);
+                return FsResult::Io(io);
+            }
+
+            self.queue.pop_front();
+        }
+
+        FsResult::Ok(mem::take(&mut self.matches))
+    }
+
+    /// Matches the given task against the directory listings read so
+    /// far, queuing new tasks for matching subdirectories.
+    ///
+    /// Returns the I/O request needed to process the task, if any.
+    fn step(&mut self, task: Task) -> Option<(Pending, FsIo)> {
+        let len = self.patterns[task.pattern].len();
+        let mut dir = task.dir;
+        let mut index = task.index;
+
+        while index + 1 < len {
+            let Segment::Literal(name) = &self.patterns[task.pattern][index] else {
+                break;
+            };
+
+            dir.push(name);
+
+            if self.rules.is_ignored(&dir, true) {
+                return None;
+            }
+
+            index += 1;
+        }
+
+        let segment = self.patterns[task.pattern][index].clone();
+        let last = index + 1 == len;
+
+        if let Segment::Literal(name) = &segment {
+            let path = dir.join(name);
+
+            if let Some(io) = self.read_ignore_files(&path) {
+                return Some(io);
+            }
+
+            match self.listings.get(&dir) {
+                Some(listing) => {
+                    let is_dir = listing.get(&path) == Some(&FsKind::Dir);
+
+                    if listing.contains_key(&path) && !self.rules.is_ignored(&path, is_dir) {
+                        self.matches.insert(path);
+                    }
+                }
+                None => {
+                    trace!("wants I/O to read metadata at {}", path.display());
+                    let io = FsIo::ReadMetadata(Err(path.clone()));
+                    return Some((Pending::Entry(path), io));
+                }
+            }
+
+            return None;
+        }
+
+        if let Some(io) = self.read_ignore_files(&dir) {
+            return Some(io);
+        }
+
+        let Some(listing) = self.listings.get(&dir) else {
+            trace!("wants I/O to read dir {}", dir.display());
+            let io = FsIo::ReadDir(Err(dir.clone()));
+            return Some((Pending::Dir(dir), io));
+        };
+
+        for name in &self.ignore_files {
+            let path = dir.join(name);
+
+            if !self.ignore_files_read.insert(path.clone()) || !listing.contains_key(&path) {
+                continue;
+            }
+
+            trace!("wants I/O to read ignore file {}", path.display());
+            let io = FsIo::ReadFile(Err(path.clone()));
+            return Some((Pending::IgnoreFile(dir, path), io));
+        }
+
+        let mut tasks = Vec::new();
+
+        if segment == Segment::Recursive && !last {
+            tasks.push(Task {
+                dir: dir.clone(),
+                pattern: task.pattern,
+                index: index + 1,
+            });
+        }
+
+        for (path, kind) in listing {
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => continue,
+            };
+
+            let hidden = name.starts_with('.');
+
+            if hidden && !self.hidden && !segment.matches_hidden() {
+                continue;
+            }
+
+            if !segment.matches(&name) || self.rules.is_ignored(path, *kind == FsKind::Dir) {
+                continue;
+            }
+
+            if last {
+                self.matches.insert(path.clone());
+            }
+
+            if *kind != FsKind::Dir {
+                continue;
+            }
+
+            match segment {
+                Segment::Recursive => tasks.push(Task {
+                    dir: path.clone(),
+                    pattern: task.pattern,
+                    index,
+                }),
+                _ if !last => tasks.push(Task {
+                    dir: path.clone(),
+                    pattern: task.pattern,
+                    index: index + 1,
+                }),
+                _ => (),
+            }
+        }
+
+        for task in tasks {
+            if self.seen.insert(task.clone()) {
+                self.queue.push_back(task);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the request reading the next ignore file of the
+    /// directories containing the given path, from the root
+    /// directory, if any.
+    ///
+    /// Those directories are not necessarily read, so ignore files
+    /// are read directly and skipped if missing.
+    fn read_ignore_files(&mut self, path: &Path) -> Option<(Pending, FsIo)> {
+        let parents = path.ancestors().skip(1);
+        let parents = parents.take_while(|parent| parent.starts_with(&self.root));
+        let parents: Vec<_> = parents.collect();
+
+        for parent in parents.into_iter().rev() {
+            for name in &self.ignore_files {
+                let path = parent.join(name);
+
+                if self.ignore_files_read.insert(path.clone()) {
+                    trace!("wants I/O to read ignore file {}", path.display());
+                    let io = FsIo::ReadFile(Err(path.clone()));
+                    return Some((Pending::IgnoreFile(parent.to_path_buf(), path), io));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns `true` if the given error means that the entry does not
+/// exist, or is not a directory.
+fn is_missing(err: &FsIoError) -> bool {
+    matches!(
+        err.kind,
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+    )
+}
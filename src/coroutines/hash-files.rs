@@ -0,0 +1,235 @@
+//! I/O-free coroutine to compute content digests of multiple
+//! filesystem files.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{self, Write},
+    mem,
+    path::PathBuf,
+};
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::{FsChunk, FsIo},
+};
+
+/// The default maximum length of chunks read per file, per I/O
+/// request.
+pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// The default maximum number of files read per I/O request.
+pub const DEFAULT_MAX_FILES: usize = 16;
+
+/// The algorithm used to compute content digests.
+///
+/// Each algorithm is available behind the feature of the same name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashAlgorithm {
+    /// The BLAKE3 algorithm, producing 32-byte digests.
+    #[cfg(feature = "blake3")]
+    Blake3,
+
+    /// The SHA-256 algorithm, producing 32-byte digests.
+    #[cfg(feature = "sha2")]
+    Sha256,
+}
+
+/// A content digest.
+#[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Digest(Vec<u8>);
+
+impl Digest {
+    /// Returns the raw digest.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the digest as a lowercase hexadecimal string.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self.to_hex())
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+enum Hasher {
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "sha2")]
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Self::Blake3(Box::default()),
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Digest::new()),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => {
+                hasher.update(chunk);
+            }
+            #[cfg(feature = "sha2")]
+            Self::Sha256(hasher) => sha2::Digest::update(hasher, chunk),
+        }
+    }
+
+    fn finalize(self) -> Digest {
+        match self {
+            #[cfg(feature = "blake3")]
+            Self::Blake3(hasher) => Digest(hasher.finalize().as_bytes().to_vec()),
+            #[cfg(feature = "sha2")]
+            Self::Sha256(hasher) => Digest(sha2::Digest::finalize(hasher).to_vec()),
+        }
+    }
+}
+
+impl fmt::Debug for Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hasher").finish_non_exhaustive()
+    }
+}
+
+/// I/O-free coroutine to compute content digests of multiple
+/// filesystem files.
+///
+/// Files are read chunk by chunk using [`FsIo::ReadChunks`]: each
+/// request reads the next chunk of a bounded number of files not
+/// fully read yet (see [`HashFiles::with_max_files`]), so that at
+/// most `chunk_size × max_files` bytes are loaded in memory at once.
+#[derive(Debug)]
+pub struct HashFiles {
+    algorithm: HashAlgorithm,
+    chunk_size: u64,
+    max_files: usize,
+    queue: VecDeque<PathBuf>,
+    pending: BTreeMap<PathBuf, (u64, Hasher)>,
+    digests: BTreeMap<PathBuf, Digest>,
+    wants_io: bool,
+}
+
+impl HashFiles {
+    /// Creates a new coroutine from the given file paths and hash
+    /// algorithm.
+    pub fn new(
+        paths: impl IntoIterator<Item = impl Into<PathBuf>>,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        let paths: BTreeSet<PathBuf> = paths.into_iter().map(Into::into).collect();
+
+        Self {
+            algorithm,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            queue: paths.into_iter().collect(),
+            pending: BTreeMap::new(),
+            digests: BTreeMap::new(),
+            wants_io: false,
+        }
+    }
+
+    /// Sets the maximum length of chunks read per file, per I/O
+    /// request.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets the maximum number of files read per I/O request.
+    ///
+    /// Other files wait until one of them is fully read.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files.max(1);
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, Digest>> {
+        match arg {
+            None if self.wants_io => return FsResult::Err(FsError::MissingInput),
+            None => (),
+            Some(arg) => {
+                debug!("resume after reading chunks");
+
+                let FsIo::ReadChunks(io) = arg else {
+                    let err = FsError::unexpected("read chunks output", arg);
+                    return FsResult::Err(err);
+                };
+
+                let chunks = match io {
+                    Ok(chunks) => chunks,
+                    Err(input) => return FsResult::Io(FsIo::ReadChunks(Err(input))),
+                };
+
+                for (path, chunk) in chunks {
+                    let Some((offset, hasher)) = self.pending.get_mut(&path) else {
+                        continue;
+                    };
+
+                    hasher.update(&chunk);
+                    *offset += chunk.len() as u64;
+
+                    // a short chunk means the end of the file
+                    if (chunk.len() as u64) < self.chunk_size {
+                        if let Some((_, hasher)) = self.pending.remove(&path) {
+                            self.digests.insert(path, hasher.finalize());
+                        }
+                    }
+                }
+            }
+        }
+
+        while self.pending.len() < self.max_files {
+            let Some(path) = self.queue.pop_front() else {
+                break;
+            };
+
+            self.pending.insert(path, (0, Hasher::new(self.algorithm)));
+        }
+
+        if self.pending.is_empty() {
+            self.wants_io = false;
+            return FsResult::Ok(mem::take(&mut self.digests));
+        }
+
+        trace!("wants I/O to read chunks of {} files", self.pending.len());
+
+        let chunks = self
+            .pending
+            .iter()
+            .map(|(path, (offset, _))| {
+                let chunk = FsChunk {
+                    offset: *offset,
+                    len: self.chunk_size,
+                };
+                (path.clone(), chunk)
+            })
+            .collect();
+
+        self.wants_io = true;
+        FsResult::Io(FsIo::ReadChunks(Err(chunks)))
+    }
+}
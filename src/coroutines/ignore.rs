@@ -0,0 +1,169 @@
+//! Gitignore-style rules to ignore filesystem entries.
+//!
+//! Rules are applied by [`Walk`] and [`Glob`], which read nested
+//! ignore files while traversing directories, see
+//! [`Walk::with_ignore_file`] and [`Glob::with_ignore_file`].
+//!
+//! [`Walk`]: crate::coroutines::walk::Walk
+//! [`Walk::with_ignore_file`]: crate::coroutines::walk::Walk::with_ignore_file
+//! [`Glob`]: crate::coroutines::glob::Glob
+//! [`Glob::with_ignore_file`]: crate::coroutines::glob::Glob::with_ignore_file
+
+use std::path::{Path, PathBuf};
+
+use crate::coroutines::glob::{parse_segments, Segment};
+
+/// The name of Git ignore files.
+pub const GITIGNORE: &str = ".gitignore";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Rule {
+    base: PathBuf,
+    segments: Vec<Segment>,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// A set of gitignore-style rules.
+///
+/// Each rule applies to entries under the directory of the ignore
+/// file it comes from. Rules follow the gitignore syntax:
+///
+/// - blank lines and lines starting with `#` are skipped,
+/// - `!` negates the rule, re-including entries,
+/// - a trailing `/` only matches directories,
+/// - a rule containing a `/` is relative to its directory, otherwise
+///   it matches at any depth,
+/// - `*`, `?`, character classes and `**` behave like in [`Glob`].
+///
+/// The last matching rule wins. Like Git, entries of an ignored
+/// directory cannot be re-included, since traversals do not descend
+/// into ignored directories.
+///
+/// [`Glob`]: crate::coroutines::glob::Glob
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Creates an empty set of rules, ignoring nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the rules of the given ignore file contents, relative to
+    /// the given base directory.
+    ///
+    /// Rules added later take precedence, so ignore files of parent
+    /// directories need to be added before their children's.
+    pub fn add(&mut self, base: impl AsRef<Path>, contents: &str) {
+        let base = base.as_ref();
+
+        for line in contents.lines() {
+            if let Some(rule) = parse_rule(base, line) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    /// Adds the rules of the given ignore file contents, relative to
+    /// the given base directory, see [`IgnoreRules::add`].
+    pub fn with_rules(mut self, base: impl AsRef<Path>, contents: &str) -> Self {
+        self.add(base, contents);
+        self
+    }
+
+    /// Returns `true` if there is no rule.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns `true` if the given entry path is ignored.
+    ///
+    /// Only the entry itself is checked, not its parent directories.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+
+        for rule in &self.rules {
+            // the rule would not change the outcome
+            if ignored != rule.negated || (rule.dir_only && !is_dir) {
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+
+            let names: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+
+            if !names.is_empty() && match_segments(&rule.segments, &names) {
+                ignored = !rule.negated;
+            }
+        }
+
+        ignored
+    }
+}
+
+fn parse_rule(base: &Path, line: &str) -> Option<Rule> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // trailing spaces are ignored, unless escaped
+    let mut line = line;
+
+    while line.ends_with(' ') && !line[..line.len() - 1].ends_with('\\') {
+        line = &line[..line.len() - 1];
+    }
+
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let anchored = line.contains('/');
+    let mut segments = parse_segments(line);
+
+    if segments.is_empty() {
+        return None;
+    }
+
+    if !anchored && segments[0] != Segment::Recursive {
+        segments.insert(0, Segment::Recursive);
+    }
+
+    Some(Rule {
+        base: base.to_path_buf(),
+        segments,
+        negated,
+        dir_only,
+    })
+}
+
+/// Matches path component names against pattern segments.
+///
+/// A trailing `**` only matches entries inside a directory, not the
+/// directory itself.
+fn match_segments(segments: &[Segment], names: &[impl AsRef<str>]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((Segment::Recursive, [])) => !names.is_empty(),
+        Some((Segment::Recursive, rest)) => {
+            (0..=names.len()).any(|i| match_segments(rest, &names[i..]))
+        }
+        Some((segment, rest)) => match names.split_first() {
+            Some((name, names)) => segment.matches(name.as_ref()) && match_segments(rest, names),
+            None => false,
+        },
+    }
+}
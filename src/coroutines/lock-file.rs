@@ -0,0 +1,281 @@
+//! I/O-free coroutine to acquire a portable, lockfile-based lock on a
+//! filesystem entry.
+
+use std::{
+    ffi::OsString,
+    io, mem,
+    path::{Path, PathBuf},
+    process, str,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, trace, warn};
+
+use crate::{
+    coroutines::{remove_file::RemoveFile, set_modified::SetModified},
+    error::{FsError, FsIoError, FsResult},
+    io::{FsIo, FsMetadata, RenameMode},
+};
+
+/// The default age after which a lock file is considered stale.
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// The maximum number of attempts to create the lock file.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Counter making stale lock file names unique within the process.
+static TAKEOVERS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    CreateNewFile,
+    ReadMetadata,
+    ReadPid(FsMetadata),
+    CheckProcess(FsMetadata),
+    RenameStale(FsMetadata, PathBuf),
+    ReadStale(FsMetadata, PathBuf),
+    RemoveStale,
+    RestoreStale,
+}
+
+/// I/O-free coroutine to acquire a portable, lockfile-based lock on a
+/// filesystem entry.
+///
+/// The lock is acquired by atomically creating a `.lock` file next to
+/// the entry, containing the identifier of the current process.
+///
+/// If the lock file already exists, it is considered abandoned when
+/// its last modification is older than the stale duration and, on
+/// Linux, when the process it records is not alive anymore. A stale
+/// lock file is taken over atomically: it is first renamed to a
+/// unique name, then removed only if it is still the stale one.
+/// Otherwise it is restored, since another process took it over in
+/// the meantime.
+///
+/// On platforms where processes cannot be checked, holders keeping
+/// the lock longer than the stale duration must refresh it, see
+/// [`LockFile::refresh`].
+///
+/// Fails with [`FsError::Io`] of kind
+/// [`io::ErrorKind::WouldBlock`] if the lock is held. The lock is
+/// released by removing the lock file, see [`LockFile::release`].
+///
+/// Like [`TransactionalRename`], the loop needs to send I/O errors
+/// back to the coroutine (see [`FsIo::Error`]).
+///
+/// [`TransactionalRename`]: crate::coroutines::transactional_rename::TransactionalRename
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    stale_after: Duration,
+    attempts: usize,
+    state: State,
+}
+
+impl LockFile {
+    /// Creates a new coroutine from the given entry path.
+    ///
+    /// The lock file path is the entry path suffixed with `.lock`.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let mut name = OsString::from(path.file_name().unwrap_or_default());
+        name.push(".lock");
+
+        Self {
+            path: path.with_file_name(name),
+            stale_after: DEFAULT_STALE_AFTER,
+            attempts: 0,
+            state: State::Idle,
+        }
+    }
+
+    /// Sets the age after which an existing lock file is considered
+    /// stale.
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Returns the lock file path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a coroutine refreshing the lock, by setting the last
+    /// modification time of the lock file to now.
+    ///
+    /// Holders should refresh the lock more often than the stale
+    /// duration, otherwise the lock may be taken over.
+    pub fn refresh(&self) -> SetModified {
+        SetModified::new([(&self.path, SystemTime::now())])
+    }
+
+    /// Returns a coroutine releasing the lock, by removing the lock
+    /// file.
+    pub fn release(&self) -> RemoveFile {
+        RemoveFile::new(&self.path)
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        let Some(arg) = arg else {
+            if !matches!(self.state, State::Idle) {
+                return FsResult::Err(FsError::MissingInput);
+            }
+
+            return self.create();
+        };
+
+        match (mem::replace(&mut self.state, State::Idle), arg) {
+            (State::CreateNewFile, FsIo::CreateNewFile(Ok(()))) => {
+                debug!("acquired lock file {}", self.path.display());
+                FsResult::Ok(())
+            }
+            (State::CreateNewFile, FsIo::Error(err))
+                if err.kind == io::ErrorKind::AlreadyExists =>
+            {
+                trace!("wants I/O to read metadata at {}", self.path.display());
+                self.state = State::ReadMetadata;
+                FsResult::Io(FsIo::ReadMetadata(Err(self.path.clone())))
+            }
+            (State::ReadMetadata, FsIo::ReadMetadata(Ok(metadata))) => {
+                let age = metadata
+                    .modified
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+                if age.is_none_or(|age| age < self.stale_after) {
+                    return self.locked();
+                }
+
+                trace!("wants I/O to read lock file {}", self.path.display());
+                self.state = State::ReadPid(metadata);
+                FsResult::Io(FsIo::ReadFile(Err(self.path.clone())))
+            }
+            (State::ReadPid(metadata), FsIo::ReadFile(Ok(contents))) => {
+                let pid = str::from_utf8(&contents)
+                    .ok()
+                    .and_then(|contents| contents.trim().parse::<u32>().ok());
+
+                match pid {
+                    Some(pid) if pid == process::id() => self.locked(),
+                    Some(pid) => match process_path(pid) {
+                        Some(path) => {
+                            trace!("wants I/O to check process {pid}");
+                            self.state = State::CheckProcess(metadata);
+                            FsResult::Io(FsIo::ReadMetadata(Err(path)))
+                        }
+                        None => self.take_over(metadata),
+                    },
+                    None => self.take_over(metadata),
+                }
+            }
+            (State::CheckProcess(_), FsIo::ReadMetadata(Ok(_))) => {
+                debug!("lock file {} holder is still alive", self.path.display());
+                self.locked()
+            }
+            (State::CheckProcess(metadata), FsIo::Error(err))
+                if err.kind == io::ErrorKind::NotFound =>
+            {
+                self.take_over(metadata)
+            }
+            (State::RenameStale(metadata, stale), FsIo::Rename(Ok(()))) => {
+                trace!("wants I/O to read metadata at {}", stale.display());
+                self.state = State::ReadStale(metadata, stale.clone());
+                FsResult::Io(FsIo::ReadMetadata(Err(stale)))
+            }
+            (State::ReadStale(metadata, stale), FsIo::ReadMetadata(Ok(current))) => {
+                if is_same_file(&metadata, &current) {
+                    warn!("remove stale lock file {}", self.path.display());
+                    self.state = State::RemoveStale;
+                    return FsResult::Io(FsIo::RemoveFile(Err(stale)));
+                }
+
+                warn!(
+                    "lock file {} taken over meanwhile, restore it",
+                    self.path.display()
+                );
+                self.state = State::RestoreStale;
+                let paths = vec![(stale, self.path.clone())];
+                FsResult::Io(FsIo::Rename(Err((paths, RenameMode::NoReplace))))
+            }
+            (State::RemoveStale, FsIo::RemoveFile(Ok(()))) => self.create(),
+            (State::RestoreStale, FsIo::Rename(Ok(()))) => self.locked(),
+            // the lock file has been removed in the meantime
+            (
+                State::ReadMetadata | State::ReadPid(_) | State::RenameStale(..),
+                FsIo::Error(err),
+            ) if err.kind == io::ErrorKind::NotFound => self.create(),
+            (_, FsIo::Error(err)) => FsResult::Err(FsError::Io(err)),
+            (
+                state,
+                io @ (FsIo::CreateNewFile(Err(_))
+                | FsIo::ReadFile(Err(_))
+                | FsIo::ReadMetadata(Err(_))
+                | FsIo::RemoveFile(Err(_))
+                | FsIo::Rename(Err(_))),
+            ) => {
+                self.state = state;
+                FsResult::Io(io)
+            }
+            (_, arg) => FsResult::Err(FsError::unexpected("lock file output", arg)),
+        }
+    }
+
+    fn create(&mut self) -> FsResult {
+        if self.attempts == MAX_ATTEMPTS {
+            return self.locked();
+        }
+
+        self.attempts += 1;
+        self.state = State::CreateNewFile;
+
+        trace!("wants I/O to create lock file {}", self.path.display());
+
+        let contents = format!("{}\n", process::id()).into_bytes();
+        FsResult::Io(FsIo::CreateNewFile(Err((self.path.clone(), contents))))
+    }
+
+    /// Renames the stale lock file to a unique name, so that it can be
+    /// checked then removed without racing with other processes.
+    fn take_over(&mut self, metadata: FsMetadata) -> FsResult {
+        let n = TAKEOVERS.fetch_add(1, Ordering::Relaxed);
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}.{n}.stale", process::id()));
+        let stale = PathBuf::from(name);
+
+        trace!("wants I/O to rename stale lock file to {}", stale.display());
+
+        self.state = State::RenameStale(metadata, stale.clone());
+        let paths = vec![(self.path.clone(), stale)];
+        FsResult::Io(FsIo::Rename(Err((paths, RenameMode::NoReplace))))
+    }
+
+    fn locked(&self) -> FsResult {
+        FsResult::Err(FsError::Io(FsIoError {
+            kind: io::ErrorKind::WouldBlock,
+            message: format!("{} is locked", self.path.display()),
+        }))
+    }
+}
+
+/// Returns the path that exists as long as the given process is
+/// alive, if any.
+#[cfg(target_os = "linux")]
+fn process_path(pid: u32) -> Option<PathBuf> {
+    Some(PathBuf::from(format!("/proc/{pid}")))
+}
+
+/// Returns the path that exists as long as the given process is
+/// alive, if any.
+#[cfg(not(target_os = "linux"))]
+fn process_path(_pid: u32) -> Option<PathBuf> {
+    None
+}
+
+/// Returns `true` if both metadata belong to the same, unmodified
+/// file.
+fn is_same_file(a: &FsMetadata, b: &FsMetadata) -> bool {
+    a.dev == b.dev && a.ino == b.ino && a.len == b.len && a.modified == b.modified
+}
@@ -0,0 +1,60 @@
+//! I/O-free coroutine to acquire an advisory lock on a filesystem
+//! entry.
+
+use std::path::PathBuf;
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::{FsIo, LockMode},
+};
+
+/// I/O-free coroutine to acquire an advisory lock on a filesystem
+/// entry.
+///
+/// The lock is held by the runtime until it is released with
+/// [`Unlock`]. Each lock has its own owner, so that locks acquired
+/// on the same entry conflict with each other, even within the same
+/// runtime.
+///
+/// Returns the identifier of the lock, to be used with [`Unlock`].
+///
+/// [`Unlock`]: crate::coroutines::unlock::Unlock
+#[derive(Debug)]
+pub struct Lock {
+    input: Option<(PathBuf, LockMode)>,
+}
+
+impl Lock {
+    /// Creates a new coroutine from the given entry path and lock
+    /// mode.
+    pub fn new(path: impl Into<PathBuf>, mode: LockMode) -> Self {
+        let input = Some((path.into(), mode));
+        Self { input }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<u64> {
+        let Some(arg) = arg else {
+            let Some((path, mode)) = self.input.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to lock {} ({mode:?})", path.display());
+            return FsResult::Io(FsIo::Lock(Err((path, mode))));
+        };
+
+        debug!("resume after locking");
+
+        let FsIo::Lock(io) = arg else {
+            let err = FsError::unexpected("lock output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(id) => FsResult::Ok(id),
+            Err(input) => FsResult::Io(FsIo::Lock(Err(input))),
+        }
+    }
+}
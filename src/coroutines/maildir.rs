@@ -0,0 +1,392 @@
+//! I/O-free coroutines to manage Maildir mailboxes.
+//!
+//! A Maildir is a directory containing three subdirectories: `tmp`
+//! where messages are written, `new` where delivered messages
+//! appear, and `cur` where messages are moved once seen by a mail
+//! client. Message flags are stored in the file name, after the
+//! `:2,` info suffix.
+//!
+//! See <https://cr.yp.to/proto/maildir.html>.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::{
+        create_dirs::CreateDirs, create_new_file::CreateNewFile, rename::Rename,
+        sync_paths::SyncPaths,
+    },
+    error::{FsError, FsIoError, FsResult},
+    io::{FsIo, RenameMode},
+};
+
+/// The separator between the unique name and the info of a message
+/// file name.
+pub const INFO_SEPARATOR: char = ':';
+
+/// The prefix of the info of a message file name, followed by
+/// flags.
+pub const INFO_PREFIX: &str = "2,";
+
+/// The default host name used to build unique message names, see
+/// [`DeliverMessage::with_hostname`].
+pub const DEFAULT_HOSTNAME: &str = "localhost";
+
+/// Deliveries made by the current process, used to build unique
+/// message names.
+static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the path of the `cur` subdirectory of the given Maildir.
+pub fn cur_dir(root: impl AsRef<Path>) -> PathBuf {
+    root.as_ref().join("cur")
+}
+
+/// Returns the path of the `new` subdirectory of the given Maildir.
+pub fn new_dir(root: impl AsRef<Path>) -> PathBuf {
+    root.as_ref().join("new")
+}
+
+/// Returns the path of the `tmp` subdirectory of the given Maildir.
+pub fn tmp_dir(root: impl AsRef<Path>) -> PathBuf {
+    root.as_ref().join("tmp")
+}
+
+/// Splits the given message file name into its unique name and its
+/// flags.
+///
+/// Names without info, or with an info other than `2,`, have no
+/// flags.
+pub fn split_flags(name: &str) -> (&str, BTreeSet<char>) {
+    match name.rsplit_once(INFO_SEPARATOR) {
+        Some((id, info)) => {
+            let flags = match info.strip_prefix(INFO_PREFIX) {
+                Some(flags) => flags.chars().collect(),
+                None => BTreeSet::new(),
+            };
+            (id, flags)
+        }
+        None => (name, BTreeSet::new()),
+    }
+}
+
+/// Builds a message file name from the given unique name and flags.
+///
+/// Flags are written in ASCII order, as required by the
+/// specification.
+pub fn join_flags(id: &str, flags: impl IntoIterator<Item = char>) -> String {
+    let flags: BTreeSet<char> = flags.into_iter().collect();
+    let flags: String = flags.into_iter().collect();
+    format!("{id}{INFO_SEPARATOR}{INFO_PREFIX}{flags}")
+}
+
+/// Generates a unique message name for the given host name, in the
+/// form `{secs}.M{micros}P{pid}Q{n}.{hostname}`.
+///
+/// Slashes and colons in the host name are escaped, as required by
+/// the specification.
+pub fn unique_name(hostname: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let micros = now.subsec_micros();
+    let pid = process::id();
+    let n = DELIVERIES.fetch_add(1, Ordering::Relaxed);
+    let hostname = hostname.replace('/', "\\057").replace(':', "\\072");
+
+    format!("{secs}.M{micros}P{pid}Q{n}.{hostname}")
+}
+
+/// Returns the file name of the given path as a string.
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(OsStr::to_str)
+}
+
+/// Returns `true` if the given message path is in the given
+/// subdirectory of a Maildir.
+fn is_in(path: &Path, dir: &str) -> bool {
+    path.parent().and_then(file_name) == Some(dir)
+}
+
+fn invalid_path(path: &Path, reason: &str) -> FsResult<PathBuf> {
+    FsResult::Err(FsError::Io(FsIoError {
+        kind: io::ErrorKind::InvalidInput,
+        message: format!("invalid Maildir message path {}: {reason}", path.display()),
+    }))
+}
+
+/// I/O-free coroutine to create a Maildir, with its `cur`, `new`
+/// and `tmp` subdirectories.
+///
+/// Fails if the Maildir directory already exists.
+#[derive(Debug)]
+pub struct CreateMaildir {
+    coroutine: CreateDirs,
+}
+
+impl CreateMaildir {
+    /// Creates a new coroutine from the given Maildir path.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let paths = [cur_dir(&root), new_dir(&root), tmp_dir(&root), root];
+        let coroutine = CreateDirs::new(paths);
+        Self { coroutine }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        self.coroutine.resume(arg)
+    }
+}
+
+#[derive(Debug)]
+enum DeliverState {
+    CreateNewFile(CreateNewFile),
+    SyncFile(SyncPaths),
+    Rename(Rename),
+}
+
+/// I/O-free coroutine to deliver a message to a Maildir.
+///
+/// The message is written to `tmp` under a unique name and flushed
+/// to disk, then moved to `new` (see [`Rename::with_durable`]). The
+/// message is therefore never visible partially written, and is on
+/// disk when the delivery succeeds.
+///
+/// Returns the path of the delivered message.
+#[derive(Debug)]
+pub struct DeliverMessage {
+    root: PathBuf,
+    contents: Option<Vec<u8>>,
+    hostname: String,
+    name: String,
+    state: Option<DeliverState>,
+}
+
+impl DeliverMessage {
+    /// Creates a new coroutine from the given Maildir path and raw
+    /// message.
+    pub fn new(root: impl Into<PathBuf>, contents: impl IntoIterator<Item = u8>) -> Self {
+        Self {
+            root: root.into(),
+            contents: Some(contents.into_iter().collect()),
+            hostname: DEFAULT_HOSTNAME.into(),
+            name: String::new(),
+            state: None,
+        }
+    }
+
+    /// Sets the host name used to build the unique message name.
+    ///
+    /// I/O-free coroutines cannot query the host name, which
+    /// defaults to [`DEFAULT_HOSTNAME`].
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<PathBuf> {
+        loop {
+            let Some(state) = &mut self.state else {
+                let Some(contents) = self.contents.take() else {
+                    return FsResult::Err(FsError::MissingInput);
+                };
+
+                self.name = unique_name(&self.hostname);
+                trace!("deliver message {} to {}", self.name, self.root.display());
+
+                let path = tmp_dir(&self.root).join(&self.name);
+                let coroutine = CreateNewFile::new(path, contents);
+                self.state = Some(DeliverState::CreateNewFile(coroutine));
+                continue;
+            };
+
+            let tmp = tmp_dir(&self.root).join(&self.name);
+            let new = new_dir(&self.root).join(&self.name);
+
+            match state {
+                DeliverState::CreateNewFile(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        let coroutine = SyncPaths::new([tmp], Vec::<PathBuf>::new());
+                        *state = DeliverState::SyncFile(coroutine);
+                    }
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                DeliverState::SyncFile(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        let coroutine = Rename::new([(tmp, new)])
+                            .with_mode(RenameMode::NoReplace)
+                            .with_durable(true);
+                        *state = DeliverState::Rename(coroutine);
+                    }
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                DeliverState::Rename(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        debug!("delivered message {}", new.display());
+                        return FsResult::Ok(new);
+                    }
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+            }
+        }
+    }
+}
+
+/// I/O-free coroutine to move a message from `new` to `cur`,
+/// marking it as seen by the mail client.
+///
+/// The message keeps its unique name, followed by an empty `:2,`
+/// info. Existing info, including flags and experimental info, is
+/// kept as is.
+///
+/// Fails with [`FsError::Io`] of kind [`io::ErrorKind::InvalidInput`]
+/// if the path is not a message of the `new` subdirectory of a
+/// Maildir.
+///
+/// Returns the new path of the message.
+#[derive(Debug)]
+pub struct MoveToCur {
+    path: PathBuf,
+    destination: Option<PathBuf>,
+    coroutine: Option<Rename>,
+}
+
+impl MoveToCur {
+    /// Creates a new coroutine from the given message path, in the
+    /// `new` subdirectory of a Maildir.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        let destination = file_name(&path).and_then(|name| {
+            if !is_in(&path, "new") {
+                return None;
+            }
+
+            let root = path.parent()?.parent()?;
+
+            let name = if name.contains(INFO_SEPARATOR) {
+                name.to_owned()
+            } else {
+                join_flags(name, [])
+            };
+
+            Some(cur_dir(root).join(name))
+        });
+
+        Self {
+            path,
+            destination,
+            coroutine: None,
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<PathBuf> {
+        let Some(destination) = &self.destination else {
+            return invalid_path(&self.path, "not in new");
+        };
+
+        let coroutine = self.coroutine.get_or_insert_with(|| {
+            trace!("move {} to {}", self.path.display(), destination.display());
+            let paths = [(self.path.clone(), destination.clone())];
+            Rename::new(paths).with_mode(RenameMode::NoReplace)
+        });
+
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => FsResult::Ok(destination.clone()),
+            FsResult::Err(err) => FsResult::Err(err),
+            FsResult::Io(io) => FsResult::Io(io),
+        }
+    }
+}
+
+/// I/O-free coroutine to change the flags of a message, by renaming
+/// its `:2,` info suffix.
+///
+/// Standard flags are `D` (draft), `F` (flagged), `P` (passed), `R`
+/// (replied), `S` (seen) and `T` (trashed). No I/O is needed if the
+/// flags do not change.
+///
+/// Fails with [`FsError::Io`] of kind [`io::ErrorKind::InvalidInput`]
+/// if the path is not a message of the `cur` subdirectory of a
+/// Maildir (see [`MoveToCur`]), or if its info is experimental
+/// (other than `2,`), since it cannot be changed without being lost.
+///
+/// Returns the new path of the message.
+#[derive(Debug)]
+pub struct SetFlags {
+    path: PathBuf,
+    destination: Option<PathBuf>,
+    coroutine: Option<Rename>,
+}
+
+impl SetFlags {
+    /// Creates a new coroutine from the given message path and the
+    /// flags to set, replacing the existing ones.
+    pub fn new(path: impl Into<PathBuf>, flags: impl IntoIterator<Item = char>) -> Self {
+        let path = path.into();
+
+        let destination = file_name(&path).and_then(|name| {
+            if !is_in(&path, "cur") {
+                return None;
+            }
+
+            if let Some((_, info)) = name.rsplit_once(INFO_SEPARATOR) {
+                if !info.starts_with(INFO_PREFIX) {
+                    return None;
+                }
+            }
+
+            let (id, _) = split_flags(name);
+            Some(path.with_file_name(join_flags(id, flags)))
+        });
+
+        Self {
+            path,
+            destination,
+            coroutine: None,
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<PathBuf> {
+        let Some(destination) = &self.destination else {
+            return invalid_path(&self.path, "not in cur, or with experimental info");
+        };
+
+        if *destination == self.path {
+            debug!("flags of {} unchanged", self.path.display());
+            return FsResult::Ok(destination.clone());
+        }
+
+        let coroutine = self.coroutine.get_or_insert_with(|| {
+            trace!(
+                "rename {} to {}",
+                self.path.display(),
+                destination.display()
+            );
+            let paths = [(self.path.clone(), destination.clone())];
+            Rename::new(paths).with_mode(RenameMode::NoReplace)
+        });
+
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => FsResult::Ok(destination.clone()),
+            FsResult::Err(err) => FsResult::Err(err),
+            FsResult::Io(io) => FsResult::Io(io),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! I/O-free coroutines to read and append mbox files.
+//!
+//! An mbox file is a concatenation of messages, each one starting
+//! with a `From ` line (the "From_" line) and followed by an empty
+//! line. This module implements the mboxrd variant: body lines
+//! matching `>*From ` are quoted with one more `>` when written, and
+//! unquoted when read, so that the transformation is reversible.
+//!
+//! See <https://www.loc.gov/preservation/digital/formats/fdd/fdd000385.shtml>.
+
+use std::{
+    collections::BTreeMap,
+    io, mem,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::{append_file::AppendFile, read_file::ReadFile},
+    error::{FsError, FsResult},
+    io::{FsChunk, FsIo},
+};
+
+/// The prefix of lines separating messages.
+const FROM: &[u8] = b"From ";
+
+/// The sender used when none is given, see [`MboxMessage::new`].
+pub const DEFAULT_SENDER: &str = "MAILER-DAEMON";
+
+/// A message of an mbox file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MboxMessage {
+    /// The From_ line, without the `From ` prefix and the line
+    /// ending, usually made of the sender and the delivery date.
+    pub from_line: String,
+
+    /// The raw message, unquoted.
+    pub contents: Vec<u8>,
+}
+
+impl MboxMessage {
+    /// Creates a new message from the given raw contents.
+    ///
+    /// The From_ line is made of [`DEFAULT_SENDER`] and the current
+    /// date, in the `asctime` format.
+    pub fn new(contents: impl IntoIterator<Item = u8>) -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self {
+            from_line: format!("{DEFAULT_SENDER} {}", asctime(secs)),
+            contents: contents.into_iter().collect(),
+        }
+    }
+
+    /// Sets the From_ line, without the `From ` prefix.
+    pub fn with_from_line(mut self, from_line: impl Into<String>) -> Self {
+        self.from_line = from_line.into();
+        self
+    }
+}
+
+/// Splits the given raw mbox file into messages.
+///
+/// Any line starting with `From ` starts a new message, contents
+/// before the first one are ignored. The empty line ending each
+/// message is removed, and quoted `>From ` lines are unquoted.
+pub fn parse(mbox: &[u8]) -> Vec<MboxMessage> {
+    let mut messages = Vec::new();
+    let mut message: Option<MboxMessage> = None;
+
+    for line in mbox.split_inclusive(|b| *b == b'\n') {
+        if let Some(from_line) = line.strip_prefix(FROM) {
+            messages.extend(message.take().map(trim_separator));
+
+            let from_line = String::from_utf8_lossy(from_line);
+            let from_line = from_line.trim_end_matches(['\r', '\n']).to_owned();
+
+            message = Some(MboxMessage {
+                from_line,
+                contents: Vec::new(),
+            });
+
+            continue;
+        }
+
+        let Some(message) = &mut message else {
+            continue;
+        };
+
+        match quoted_from(line) {
+            true => message.contents.extend_from_slice(&line[1..]),
+            false => message.contents.extend_from_slice(line),
+        }
+    }
+
+    messages.extend(message.map(trim_separator));
+    messages
+}
+
+/// Formats the given messages as a raw mbox file.
+///
+/// Lines matching `>*From ` are quoted, and each message is ended
+/// with a line ending, if missing, followed by an empty line.
+pub fn format<'a>(messages: impl IntoIterator<Item = &'a MboxMessage>) -> Vec<u8> {
+    let mut mbox = Vec::new();
+
+    for message in messages {
+        mbox.extend_from_slice(FROM);
+        mbox.extend_from_slice(message.from_line.as_bytes());
+        mbox.push(b'\n');
+
+        for line in message.contents.split_inclusive(|b| *b == b'\n') {
+            if line.starts_with(FROM) || quoted_from(line) {
+                mbox.push(b'>');
+            }
+
+            mbox.extend_from_slice(line);
+        }
+
+        if !mbox.ends_with(b"\n") {
+            mbox.push(b'\n');
+        }
+
+        mbox.push(b'\n');
+    }
+
+    mbox
+}
+
+/// Returns `true` if the given line matches `>+From `.
+fn quoted_from(line: &[u8]) -> bool {
+    let quotes = line.iter().take_while(|b| **b == b'>').count();
+    quotes > 0 && line[quotes..].starts_with(FROM)
+}
+
+/// Removes the empty line ending the given message.
+fn trim_separator(mut message: MboxMessage) -> MboxMessage {
+    for separator in [&b"\r\n"[..], b"\n"] {
+        let Some(contents) = message.contents.strip_suffix(separator) else {
+            continue;
+        };
+
+        if contents.ends_with(b"\n") {
+            let len = contents.len();
+            message.contents.truncate(len);
+            break;
+        }
+    }
+
+    message
+}
+
+/// Formats the given Unix timestamp in the `asctime` format, for
+/// example `Thu Jan  1 00:00:00 1970`.
+fn asctime(secs: u64) -> String {
+    const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let days = secs / 86400;
+    let time = secs % 86400;
+    let (hours, minutes, seconds) = (time / 3600, time % 3600 / 60, time % 60);
+
+    // civil date from days since epoch, see
+    // <https://howardhinnant.github.io/date_algorithms.html>
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    // the epoch was a Thursday
+    let weekday = DAYS[((days + 4) % 7) as usize];
+    let month = MONTHS[(month - 1) as usize];
+
+    format!("{weekday} {month} {day:2} {hours:02}:{minutes:02}:{seconds:02} {year}")
+}
+
+/// I/O-free coroutine to read messages of an mbox file, see
+/// [`parse`].
+#[derive(Debug)]
+pub struct ReadMbox {
+    coroutine: ReadFile,
+}
+
+impl ReadMbox {
+    /// Creates a new coroutine from the given mbox file path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let coroutine = ReadFile::new(path);
+        Self { coroutine }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<Vec<MboxMessage>> {
+        match self.coroutine.resume(arg) {
+            FsResult::Ok(contents) => {
+                let messages = parse(&contents);
+                debug!("parsed {} mbox messages", messages.len());
+                FsResult::Ok(messages)
+            }
+            FsResult::Err(err) => FsResult::Err(err),
+            FsResult::Io(io) => FsResult::Io(io),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum AppendState {
+    Idle,
+    ReadMetadata,
+    ReadLastByte,
+    AppendFile(AppendFile),
+}
+
+/// I/O-free coroutine to append messages to an mbox file, see
+/// [`format`].
+///
+/// The file is created if it does not exist. If the file does not
+/// end with a line ending, one is appended first, so that the first
+/// `From ` line starts a new line. All messages are then appended at
+/// once, using [`FsIo::AppendFile`].
+///
+/// Since the file may not exist, the loop needs to send I/O errors
+/// back to the coroutine (see [`FsIo::Error`]).
+#[derive(Debug)]
+pub struct AppendMbox {
+    path: PathBuf,
+    contents: Vec<u8>,
+    state: AppendState,
+}
+
+impl AppendMbox {
+    /// Creates a new coroutine from the given mbox file path and
+    /// messages.
+    pub fn new<'a>(
+        path: impl Into<PathBuf>,
+        messages: impl IntoIterator<Item = &'a MboxMessage>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            contents: format(messages),
+            state: AppendState::Idle,
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        if let AppendState::AppendFile(coroutine) = &mut self.state {
+            return coroutine.resume(arg);
+        }
+
+        let Some(arg) = arg else {
+            if !matches!(self.state, AppendState::Idle) {
+                return FsResult::Err(FsError::MissingInput);
+            }
+
+            // chunks are read from the file the path links to, if any
+            trace!(
+                "wants I/O to read target metadata at {}",
+                self.path.display()
+            );
+            self.state = AppendState::ReadMetadata;
+            return FsResult::Io(FsIo::ReadTargetMetadata(Err(self.path.clone())));
+        };
+
+        match (&self.state, arg) {
+            (AppendState::ReadMetadata, FsIo::ReadTargetMetadata(Ok(metadata))) => {
+                if metadata.len == 0 {
+                    return self.append();
+                }
+
+                trace!("wants I/O to read last byte of {}", self.path.display());
+                self.state = AppendState::ReadLastByte;
+                let chunk = FsChunk {
+                    offset: metadata.len - 1,
+                    len: 1,
+                };
+                let chunks = BTreeMap::from_iter([(self.path.clone(), chunk)]);
+                FsResult::Io(FsIo::ReadChunks(Err(chunks)))
+            }
+            (AppendState::ReadMetadata, FsIo::Error(err))
+                if err.kind == io::ErrorKind::NotFound =>
+            {
+                self.append()
+            }
+            (AppendState::ReadLastByte, FsIo::ReadChunks(Ok(mut chunks))) => {
+                let last = chunks.remove(&self.path).unwrap_or_default();
+
+                if last.first().is_some_and(|byte| *byte != b'\n') {
+                    debug!("end {} with a line ending first", self.path.display());
+                    self.contents.insert(0, b'\n');
+                }
+
+                self.append()
+            }
+            (_, FsIo::Error(err)) => FsResult::Err(FsError::Io(err)),
+            (_, io @ (FsIo::ReadTargetMetadata(Err(_)) | FsIo::ReadChunks(Err(_)))) => {
+                FsResult::Io(io)
+            }
+            (_, arg) => FsResult::Err(FsError::unexpected("append mbox output", arg)),
+        }
+    }
+
+    fn append(&mut self) -> FsResult {
+        let contents = mem::take(&mut self.contents);
+        let mut coroutine = AppendFile::new(&self.path, contents);
+        let result = coroutine.resume(None);
+        self.state = AppendState::AppendFile(coroutine);
+        result
+    }
+}
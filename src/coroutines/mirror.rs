@@ -0,0 +1,459 @@
+//! I/O-free coroutine to make a destination directory tree match a
+//! source directory tree.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt, mem,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    coroutines::{
+        create_dirs::CreateDirs,
+        create_files::CreateFiles,
+        diff_trees::{candidates, diff, relativize, Compare, CompareContents, DEFAULT_BATCH_SIZE},
+        read_files::ReadFiles,
+        remove_dirs::RemoveDirs,
+        remove_files::RemoveFiles,
+        rename::Rename,
+        set_modified::SetModified,
+        walk::Walk,
+    },
+    error::FsResult,
+    io::{FsIo, FsKind, FsMetadata},
+};
+
+/// An action taken, or planned, by the [`Mirror`] coroutine.
+///
+/// All paths are relative to the root directories.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MirrorAction {
+    /// Remove a file from the destination tree.
+    RemoveFile(PathBuf),
+
+    /// Create a directory in the destination tree.
+    CreateDir(PathBuf),
+
+    /// Move a file within the destination tree, from the first path
+    /// to the second one.
+    Move(PathBuf, PathBuf),
+
+    /// Remove a directory from the destination tree.
+    RemoveDir(PathBuf),
+
+    /// Copy a file from the source tree to the destination tree.
+    CopyFile(PathBuf),
+}
+
+impl fmt::Display for MirrorAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemoveFile(path) => write!(f, "remove file {}", path.display()),
+            Self::CreateDir(path) => write!(f, "create directory {}", path.display()),
+            Self::Move(from, to) => write!(f, "move {} to {}", from.display(), to.display()),
+            Self::RemoveDir(path) => write!(f, "remove directory {}", path.display()),
+            Self::CopyFile(path) => write!(f, "copy file {}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    WalkSource(Walk),
+    WalkDestination(Walk),
+    CompareContents(CompareContents),
+    RemoveFiles(RemoveFiles),
+    CreateDirs(CreateDirs),
+    Rename(Rename),
+    RemoveDirs(RemoveDirs),
+    ReadFiles(ReadFiles),
+    CreateFiles(CreateFiles),
+    SetModified(SetModified),
+}
+
+/// I/O-free coroutine to make a destination directory tree match a
+/// source directory tree, rsync-style.
+///
+/// Both trees are walked using [`Walk`], then diffed. Directories
+/// and regular files of the source tree are mirrored, other entries
+/// are ignored. The destination root directory must exist.
+///
+/// Changes are applied in the following order, one batch request
+/// per step: conflicting then extraneous files are removed, missing
+/// directories are created, moved files are renamed, conflicting
+/// then extraneous directories are removed, and new or modified
+/// files are copied. Copies are read and written by batches bounded
+/// in bytes (see [`Mirror::with_batch_size`]), and keep the last
+/// modification time of their source file.
+///
+/// Files moved within the source tree are only renamed within the
+/// destination tree when extraneous entries are deleted, otherwise
+/// they are copied.
+///
+/// Returns the actions taken, or the ones that would have been taken
+/// when running in dry-run mode.
+#[derive(Debug)]
+pub struct Mirror {
+    source: PathBuf,
+    destination: PathBuf,
+    compare: Compare,
+    batch_size: u64,
+    delete: bool,
+    dry_run: bool,
+    source_entries: BTreeMap<PathBuf, FsMetadata>,
+    destination_entries: BTreeMap<PathBuf, FsMetadata>,
+    equal: BTreeSet<(PathBuf, PathBuf)>,
+    actions: Vec<MirrorAction>,
+    plan: VecDeque<State>,
+    copies: VecDeque<PathBuf>,
+    batch: Vec<PathBuf>,
+    state: State,
+}
+
+impl Mirror {
+    /// Creates a new coroutine from the given source and destination
+    /// root directory paths.
+    pub fn new(source: impl Into<PathBuf>, destination: impl Into<PathBuf>) -> Self {
+        let source = source.into();
+        let destination = destination.into();
+        let state = State::WalkSource(Walk::new(&source));
+
+        Self {
+            source,
+            destination,
+            compare: Compare::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            delete: false,
+            dry_run: false,
+            source_entries: BTreeMap::new(),
+            destination_entries: BTreeMap::new(),
+            equal: BTreeSet::new(),
+            actions: Vec::new(),
+            plan: VecDeque::new(),
+            copies: VecDeque::new(),
+            batch: Vec::new(),
+            state,
+        }
+    }
+
+    /// Sets the strategy used to compare files.
+    pub fn with_compare(mut self, compare: Compare) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Sets the maximum number of bytes read by a single request when
+    /// comparing files by [`Compare::Content`] (see
+    /// [`DiffTrees::with_batch_size`]) and when copying files.
+    ///
+    /// A batch always holds at least one file, whatever its size.
+    ///
+    /// [`DiffTrees::with_batch_size`]: crate::coroutines::diff_trees::DiffTrees::with_batch_size
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Deletes destination entries that do not exist in the source
+    /// tree.
+    pub fn with_delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Only plans actions, without applying them.
+    ///
+    /// Both trees are still walked, and files are still read when
+    /// comparing by [`Compare::Content`].
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<Vec<MirrorAction>> {
+        loop {
+            match &mut self.state {
+                State::WalkSource(coroutine) => {
+                    let entries = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(entries) => entries,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    self.source_entries = relativize(&self.source, entries);
+                    self.state = State::WalkDestination(Walk::new(&self.destination));
+                    continue;
+                }
+                State::WalkDestination(coroutine) => {
+                    let entries = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(entries) => entries,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    self.destination_entries = relativize(&self.destination, entries);
+
+                    let source = mirrored(&self.source_entries);
+                    let candidates = match self.compare {
+                        Compare::SizeAndModified => BTreeMap::new(),
+                        Compare::Content => candidates(&self.destination_entries, &source),
+                    };
+
+                    if candidates.is_empty() {
+                        self.plan();
+                    } else {
+                        debug!("compare contents of {} groups of files", candidates.len());
+                        let roots = (self.destination.clone(), self.source.clone());
+                        let coroutine = CompareContents::new(roots, candidates, self.batch_size);
+                        self.state = State::CompareContents(coroutine);
+                        continue;
+                    }
+                }
+                State::CompareContents(coroutine) => {
+                    self.equal = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(equal) => equal,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    self.plan();
+                }
+                State::RemoveFiles(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => (),
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                State::CreateDirs(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => (),
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                State::Rename(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => (),
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                State::RemoveDirs(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => (),
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                State::ReadFiles(coroutine) => {
+                    let contents = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(contents) => contents,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let state = self.create_files(contents);
+                    self.plan.push_front(state);
+                }
+                State::CreateFiles(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        let state = self.set_modified();
+                        self.plan.push_front(state);
+                    }
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                State::SetModified(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        if let Some(state) = self.read_files() {
+                            self.plan.push_front(state);
+                        }
+                    }
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+            }
+
+            match self.plan.pop_front() {
+                Some(state) => self.state = state,
+                None => return FsResult::Ok(mem::take(&mut self.actions)),
+            }
+        }
+    }
+
+    /// Plans actions based on both trees entries, then queues the
+    /// batch requests needed to apply them.
+    fn plan(&mut self) {
+        let source = mirrored(&self.source_entries);
+        let destination = &self.destination_entries;
+        let diff = diff(destination, &source, self.compare, &self.equal);
+
+        let kind = |entries: &BTreeMap<PathBuf, FsMetadata>, path: &Path| entries[path].kind;
+
+        // destination entries in the way of source entries of another
+        // kind are removed, regardless of the delete option
+        let mut conflicts = BTreeSet::new();
+
+        for path in &diff.modified {
+            if kind(destination, path) != kind(&source, path) {
+                conflicts.insert(path.clone());
+            }
+        }
+
+        let moves = if self.delete {
+            diff.moved.clone()
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut removed: BTreeSet<_> = destination
+            .keys()
+            .filter(|path| conflicts.iter().any(|conflict| path.starts_with(conflict)))
+            .cloned()
+            .collect();
+
+        if self.delete {
+            removed.extend(diff.removed);
+        }
+
+        let (removed_dirs, removed_files): (BTreeSet<_>, BTreeSet<_>) = removed
+            .into_iter()
+            .filter(|path| !moves.contains_key(path))
+            .partition(|path| kind(destination, path) == FsKind::Dir);
+
+        let mut created_dirs = BTreeSet::new();
+        let mut copies = BTreeSet::new();
+
+        for path in diff.added.iter().chain(&conflicts) {
+            if kind(&source, path) == FsKind::Dir {
+                created_dirs.insert(path.clone());
+            } else {
+                copies.insert(path.clone());
+            }
+        }
+
+        for path in diff.modified.iter().chain(diff.moved.values()) {
+            if kind(&source, path) == FsKind::File && !moves.values().any(|to| to == path) {
+                copies.insert(path.clone());
+            }
+        }
+
+        let actions = &mut self.actions;
+        actions.extend(removed_files.iter().cloned().map(MirrorAction::RemoveFile));
+        actions.extend(created_dirs.iter().cloned().map(MirrorAction::CreateDir));
+        actions.extend(
+            moves
+                .iter()
+                .map(|(a, b)| MirrorAction::Move(a.clone(), b.clone())),
+        );
+        actions.extend(
+            removed_dirs
+                .iter()
+                .rev()
+                .cloned()
+                .map(MirrorAction::RemoveDir),
+        );
+        actions.extend(copies.iter().cloned().map(MirrorAction::CopyFile));
+
+        debug!("planned {} mirror actions", actions.len());
+
+        if self.dry_run {
+            return;
+        }
+
+        let destination = |paths: BTreeSet<PathBuf>| -> Vec<PathBuf> {
+            paths
+                .into_iter()
+                .map(|path| self.destination.join(path))
+                .collect()
+        };
+
+        if !removed_files.is_empty() {
+            let paths = destination(removed_files);
+            self.plan
+                .push_back(State::RemoveFiles(RemoveFiles::new(paths)));
+        }
+
+        if !created_dirs.is_empty() {
+            let paths = destination(created_dirs);
+            self.plan
+                .push_back(State::CreateDirs(CreateDirs::new(paths)));
+        }
+
+        if !moves.is_empty() {
+            let paths = moves
+                .into_iter()
+                .map(|(from, to)| (self.destination.join(from), self.destination.join(to)));
+            self.plan.push_back(State::Rename(Rename::new(paths)));
+        }
+
+        if !removed_dirs.is_empty() {
+            let paths = destination(removed_dirs);
+            self.plan
+                .push_back(State::RemoveDirs(RemoveDirs::new(paths)));
+        }
+
+        self.copies = copies.into_iter().collect();
+
+        if let Some(state) = self.read_files() {
+            self.plan.push_back(state);
+        }
+    }
+
+    /// Builds the request reading the next batch of source files to
+    /// copy, if any.
+    fn read_files(&mut self) -> Option<State> {
+        let mut size = 0;
+
+        while let Some(path) = self.copies.front() {
+            let len = self.source_entries[path].len;
+
+            if !self.batch.is_empty() && size + len > self.batch_size {
+                break;
+            }
+
+            size += len;
+            self.batch.extend(self.copies.pop_front());
+        }
+
+        if self.batch.is_empty() {
+            return None;
+        }
+
+        debug!("copy batch of {} files ({size} bytes)", self.batch.len());
+        let paths: Vec<_> = self
+            .batch
+            .iter()
+            .map(|path| self.source.join(path))
+            .collect();
+        Some(State::ReadFiles(ReadFiles::new(paths)))
+    }
+
+    /// Builds the request writing the current batch of source files
+    /// contents to the destination tree.
+    fn create_files(&self, mut contents: BTreeMap<PathBuf, Vec<u8>>) -> State {
+        let contents = self.batch.iter().map(|path| {
+            let contents = contents.remove(&self.source.join(path)).unwrap_or_default();
+            (self.destination.join(path), contents)
+        });
+
+        State::CreateFiles(CreateFiles::new(contents.collect::<Vec<_>>()))
+    }
+
+    /// Builds the request giving the current batch of copies the last
+    /// modification time of their source file.
+    fn set_modified(&mut self) -> State {
+        let times = mem::take(&mut self.batch).into_iter().filter_map(|path| {
+            let time = self.source_entries[&path].modified?;
+            Some((self.destination.join(path), time))
+        });
+
+        State::SetModified(SetModified::new(times.collect::<Vec<_>>()))
+    }
+}
+
+/// Keeps only directories and regular files.
+fn mirrored(entries: &BTreeMap<PathBuf, FsMetadata>) -> BTreeMap<PathBuf, FsMetadata> {
+    entries
+        .iter()
+        .filter(|(_, m)| matches!(m.kind, FsKind::Dir | FsKind::File))
+        .map(|(path, metadata)| (path.clone(), metadata.clone()))
+        .collect()
+}
@@ -4,9 +4,21 @@
 //! Coroutines emit [I/O] requests that need to be processed by
 //! [runtimes] in order to continue their progression.
 //!
+//! All coroutines are driven the same way, by sending back the I/O
+//! response to `resume`. When the runtime fails to process a request,
+//! the loop can either stop, or send the I/O error back as
+//! [`FsIo::Error`]: coroutines able to recover from errors, like
+//! [`TransactionalRename`], need it, other coroutines fail with
+//! [`FsError::Io`].
+//!
 //! [I/O]: crate::io
 //! [runtimes]: crate::runtimes
+//! [`FsIo::Error`]: crate::io::FsIo::Error
+//! [`TransactionalRename`]: transactional_rename::TransactionalRename
+//! [`FsError::Io`]: crate::error::FsError::Io
 
+#[path = "append-file.rs"]
+pub mod append_file;
 #[path = "create-dir.rs"]
 pub mod create_dir;
 #[path = "create-dirs.rs"]
@@ -15,12 +27,41 @@ pub mod create_dirs;
 pub mod create_file;
 #[path = "create-files.rs"]
 pub mod create_files;
+#[path = "create-new-file.rs"]
+pub mod create_new_file;
+#[path = "detect-changes.rs"]
+pub mod detect_changes;
+#[path = "diff-trees.rs"]
+pub mod diff_trees;
+#[path = "disk-usage.rs"]
+pub mod disk_usage;
+pub mod glob;
+#[cfg(any(feature = "blake3", feature = "sha2"))]
+#[path = "hash-files.rs"]
+pub mod hash_files;
+pub mod ignore;
+pub mod lock;
+#[path = "lock-file.rs"]
+pub mod lock_file;
+pub mod maildir;
+pub mod mbox;
+pub mod mirror;
+#[path = "poll-events.rs"]
+pub mod poll_events;
 #[path = "read-dir.rs"]
 pub mod read_dir;
+#[path = "read-dir-sorted.rs"]
+pub mod read_dir_sorted;
 #[path = "read-file.rs"]
 pub mod read_file;
 #[path = "read-files.rs"]
 pub mod read_files;
+#[path = "read-metadata.rs"]
+pub mod read_metadata;
+#[path = "read-metadatas.rs"]
+pub mod read_metadatas;
+#[path = "read-target-metadata.rs"]
+pub mod read_target_metadata;
 #[path = "remove-dir.rs"]
 pub mod remove_dir;
 #[path = "remove-dirs.rs"]
@@ -30,3 +71,14 @@ pub mod remove_file;
 #[path = "remove-files.rs"]
 pub mod remove_files;
 pub mod rename;
+#[path = "set-modified.rs"]
+pub mod set_modified;
+#[path = "sync-paths.rs"]
+pub mod sync_paths;
+#[path = "transactional-rename.rs"]
+pub mod transactional_rename;
+pub mod unlock;
+pub mod unwatch;
+pub mod vdir;
+pub mod walk;
+pub mod watch;
@@ -0,0 +1,54 @@
+//! I/O-free coroutine to poll events of a filesystem watcher.
+
+use std::time::Duration;
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::{FsEvent, FsIo},
+};
+
+/// I/O-free coroutine to poll events of a filesystem watcher, see
+/// [`Watch`].
+///
+/// Returns an empty list if no event occurred before the timeout.
+///
+/// [`Watch`]: crate::coroutines::watch::Watch
+#[derive(Debug)]
+pub struct PollEvents {
+    input: Option<(u64, Duration)>,
+}
+
+impl PollEvents {
+    /// Creates a new coroutine from the given watcher identifier and
+    /// timeout.
+    pub fn new(id: u64, timeout: Duration) -> Self {
+        let input = Some((id, timeout));
+        Self { input }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<Vec<FsEvent>> {
+        let Some(arg) = arg else {
+            let Some(input) = self.input.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to poll events of watcher {}", input.0);
+            return FsResult::Io(FsIo::PollEvents(Err(input)));
+        };
+
+        debug!("resume after polling events");
+
+        let FsIo::PollEvents(io) = arg else {
+            let err = FsError::unexpected("poll events output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(events) => FsResult::Ok(events),
+            Err(input) => FsResult::Io(FsIo::PollEvents(Err(input))),
+        }
+    }
+}
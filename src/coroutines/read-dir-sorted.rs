@@ -0,0 +1,98 @@
+//! I/O-free coroutine to read entries contained inside a filesystem
+//! directory, sorted by a given key.
+
+use std::path::PathBuf;
+
+use log::debug;
+
+use crate::{
+    coroutines::{read_dir::ReadDir, read_metadatas::ReadMetadatas},
+    error::FsResult,
+    io::FsIo,
+};
+
+/// The key used to sort directory entries.
+///
+/// Entries sharing the same key are sorted by path.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortKey {
+    /// Sort entries by path.
+    #[default]
+    Name,
+
+    /// Sort entries by last modification time, oldest first.
+    ///
+    /// Entries without modification time come first.
+    Modified,
+
+    /// Sort entries by size, smallest first.
+    Size,
+}
+
+#[derive(Debug)]
+enum State {
+    ReadDir(ReadDir),
+    ReadMetadatas(ReadMetadatas),
+}
+
+/// I/O-free coroutine to read entries contained inside a filesystem
+/// directory, sorted by a given key.
+///
+/// Sorting by name only needs to read the directory, whereas other
+/// keys also need to read metadata of all entries.
+#[derive(Debug)]
+pub struct ReadDirSorted {
+    key: SortKey,
+    state: State,
+}
+
+impl ReadDirSorted {
+    /// Creates a new coroutine from the given directory path and
+    /// sort key.
+    pub fn new(path: impl Into<PathBuf>, key: SortKey) -> Self {
+        let state = State::ReadDir(ReadDir::new(path));
+        Self { key, state }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<Vec<PathBuf>> {
+        loop {
+            match &mut self.state {
+                State::ReadDir(coroutine) => {
+                    let paths = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(paths) => paths,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    if self.key == SortKey::Name || paths.is_empty() {
+                        return FsResult::Ok(paths.into_iter().collect());
+                    }
+
+                    debug!("read metadata of {} entries to sort them", paths.len());
+                    self.state = State::ReadMetadatas(ReadMetadatas::new(paths));
+                }
+                State::ReadMetadatas(coroutine) => {
+                    let metadatas = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(metadatas) => metadatas,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    // metadatas are already sorted by path, and the
+                    // sort is stable
+                    let mut entries: Vec<_> = metadatas.into_iter().collect();
+
+                    match self.key {
+                        SortKey::Name => (),
+                        SortKey::Modified => entries.sort_by_key(|(_, m)| m.modified),
+                        SortKey::Size => entries.sort_by_key(|(_, m)| m.len),
+                    }
+
+                    let paths = entries.into_iter().map(|(path, _)| path).collect();
+                    return FsResult::Ok(paths);
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //! I/O-free coroutine to read entries contained inside a filesystem
 //! directory.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{collections::BTreeSet, path::PathBuf};
 
 use log::{debug, trace};
 
@@ -25,7 +25,7 @@ impl ReadDir {
     }
 
     /// Makes the coroutine progress.
-    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<HashSet<PathBuf>> {
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeSet<PathBuf>> {
         let Some(arg) = arg else {
             let Some(path) = self.path.take() else {
                 return FsResult::Err(FsError::MissingInput);
@@ -38,7 +38,7 @@ impl ReadDir {
         debug!("resume after reading directory");
 
         let FsIo::ReadDir(io) = arg else {
-            let err = FsError::InvalidArgument("read dir output", arg);
+            let err = FsError::unexpected("read dir output", arg);
             return FsResult::Err(err);
         };
 
@@ -36,7 +36,7 @@ impl ReadFile {
         debug!("resume after reading file");
 
         let FsIo::ReadFile(io) = arg else {
-            let err = FsError::InvalidArgument("read file output", arg);
+            let err = FsError::unexpected("read file output", arg);
             return FsResult::Err(err);
         };
 
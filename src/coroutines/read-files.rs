@@ -1,7 +1,7 @@
 //! I/O-free coroutine to read multiple filesystem files contents.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet},
     path::PathBuf,
 };
 
@@ -15,7 +15,7 @@ use crate::{
 /// I/O-free coroutine to read multiple filesystem files contents.
 #[derive(Debug)]
 pub struct ReadFiles {
-    paths: Option<HashSet<PathBuf>>,
+    paths: Option<BTreeSet<PathBuf>>,
 }
 
 impl ReadFiles {
@@ -26,7 +26,7 @@ impl ReadFiles {
     }
 
     /// Makes the coroutine progress.
-    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<HashMap<PathBuf, Vec<u8>>> {
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, Vec<u8>>> {
         let Some(arg) = arg else {
             let Some(path) = self.paths.take() else {
                 return FsResult::Err(FsError::MissingInput);
@@ -39,7 +39,7 @@ impl ReadFiles {
         debug!("resume after reading files");
 
         let FsIo::ReadFiles(io) = arg else {
-            let err = FsError::InvalidArgument("read files output", arg);
+            let err = FsError::unexpected("read files output", arg);
             return FsResult::Err(err);
         };
 
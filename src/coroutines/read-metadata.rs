@@ -0,0 +1,49 @@
+//! I/O-free coroutine to read filesystem entry metadata.
+
+use std::path::PathBuf;
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::{FsIo, FsMetadata},
+};
+
+/// I/O-free coroutine to read filesystem entry metadata, without
+/// following symbolic links.
+#[derive(Debug)]
+pub struct ReadMetadata {
+    path: Option<PathBuf>,
+}
+
+impl ReadMetadata {
+    /// Creates a new coroutine from the given entry path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = Some(path.into());
+        Self { path }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<FsMetadata> {
+        let Some(arg) = arg else {
+            let Some(path) = self.path.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to read metadata at {}", path.display());
+            return FsResult::Io(FsIo::ReadMetadata(Err(path)));
+        };
+
+        debug!("resume after reading metadata");
+
+        let FsIo::ReadMetadata(io) = arg else {
+            let err = FsError::unexpected("read metadata output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(metadata) => FsResult::Ok(metadata),
+            Err(path) => FsResult::Io(FsIo::ReadMetadata(Err(path))),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! I/O-free coroutine to read multiple filesystem entries metadata.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::{FsIo, FsMetadata},
+};
+
+/// I/O-free coroutine to read multiple filesystem entries metadata,
+/// without following symbolic links.
+#[derive(Debug)]
+pub struct ReadMetadatas {
+    paths: Option<BTreeSet<PathBuf>>,
+}
+
+impl ReadMetadatas {
+    /// Creates a new coroutine from the given entry paths.
+    pub fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        let paths = Some(paths.into_iter().map(Into::into).collect());
+        Self { paths }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, FsMetadata>> {
+        let Some(arg) = arg else {
+            let Some(path) = self.paths.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to read metadatas");
+            return FsResult::Io(FsIo::ReadMetadatas(Err(path)));
+        };
+
+        debug!("resume after reading metadatas");
+
+        let FsIo::ReadMetadatas(io) = arg else {
+            let err = FsError::unexpected("read metadatas output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(metadatas) => FsResult::Ok(metadatas),
+            Err(path) => FsResult::Io(FsIo::ReadMetadatas(Err(path))),
+        }
+    }
+}
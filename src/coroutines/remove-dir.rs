@@ -36,7 +36,7 @@ impl RemoveDir {
         debug!("resume after creating directory");
 
         let FsIo::RemoveDir(io) = arg else {
-            let err = FsError::InvalidArgument("remove dir output", arg);
+            let err = FsError::unexpected("remove dir output", arg);
             return FsResult::Err(err);
         };
 
@@ -1,6 +1,6 @@
 //! I/O-free coroutine to remove multiple filesystem directories.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{collections::BTreeSet, path::PathBuf};
 
 use log::{debug, trace};
 
@@ -12,7 +12,7 @@ use crate::{
 /// I/O-free coroutine to remove multiple filesystem directories.
 #[derive(Debug)]
 pub struct RemoveDirs {
-    paths: Option<HashSet<PathBuf>>,
+    paths: Option<BTreeSet<PathBuf>>,
 }
 
 impl RemoveDirs {
@@ -36,7 +36,7 @@ impl RemoveDirs {
         debug!("resume after creating directories");
 
         let FsIo::RemoveDirs(io) = arg else {
-            let err = FsError::InvalidArgument("remove dirs output", arg);
+            let err = FsError::unexpected("remove dirs output", arg);
             return FsResult::Err(err);
         };
 
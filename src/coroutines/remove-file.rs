@@ -36,7 +36,7 @@ impl RemoveFile {
         debug!("resume after creating fileectory");
 
         let FsIo::RemoveFile(io) = arg else {
-            let err = FsError::InvalidArgument("remove file output", arg);
+            let err = FsError::unexpected("remove file output", arg);
             return FsResult::Err(err);
         };
 
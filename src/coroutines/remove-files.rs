@@ -1,6 +1,6 @@
 //! I/O-free coroutine to remove multiple filesystem files.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{collections::BTreeSet, path::PathBuf};
 
 use log::{debug, trace};
 
@@ -12,7 +12,7 @@ use crate::{
 /// I/O-free coroutine to remove multiple filesystem files.
 #[derive(Debug)]
 pub struct RemoveFiles {
-    paths: Option<HashSet<PathBuf>>,
+    paths: Option<BTreeSet<PathBuf>>,
 }
 
 impl RemoveFiles {
@@ -36,7 +36,7 @@ impl RemoveFiles {
         debug!("resume after creating fileectories");
 
         let FsIo::RemoveFiles(io) = arg else {
-            let err = FsError::InvalidArgument("remove files output", arg);
+            let err = FsError::unexpected("remove files output", arg);
             return FsResult::Err(err);
         };
 
@@ -6,15 +6,23 @@ use std::path::PathBuf;
 use log::{debug, trace};
 
 use crate::{
+    coroutines::sync_paths::{parents, SyncPaths},
     error::{FsError, FsResult},
-    io::FsIo,
+    io::{FsIo, RenameMode},
 };
 
 /// I/O-free coroutine to rename multiple filesystem files and/or
 /// directories.
+///
+/// Destinations are replaced by default, see [`Rename::with_mode`].
+/// Renames are not flushed to disk by default, see
+/// [`Rename::with_durable`].
 #[derive(Debug)]
 pub struct Rename {
     sources: Option<Vec<(PathBuf, PathBuf)>>,
+    mode: RenameMode,
+    durable: Option<Vec<PathBuf>>,
+    sync: Option<SyncPaths>,
 }
 
 impl Rename {
@@ -30,29 +38,65 @@ impl Rename {
 
         Self {
             sources: Some(sources),
+            mode: RenameMode::default(),
+            durable: None,
+            sync: None,
         }
     }
 
+    /// Sets the rename mode, which defines how existing destinations
+    /// are handled.
+    pub fn with_mode(mut self, mode: RenameMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Flushes the parent directories of sources and destinations
+    /// to disk once renamed, using [`SyncPaths`].
+    ///
+    /// Renaming only changes directory entries: the contents of
+    /// renamed files need to be flushed beforehand, for example by
+    /// [`CreateFile::with_durable`].
+    ///
+    /// [`CreateFile::with_durable`]: crate::coroutines::create_file::CreateFile::with_durable
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = match &self.sources {
+            Some(sources) if durable => {
+                let paths = sources.iter().flat_map(|(from, to)| [from, to]);
+                Some(parents(paths).into_iter().collect())
+            }
+            _ => None,
+        };
+        self
+    }
+
     /// Makes the coroutine progress.
     pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        if let Some(sync) = &mut self.sync {
+            return sync.resume(arg);
+        }
+
         let Some(arg) = arg else {
             let Some(sources) = self.sources.take() else {
                 return FsResult::Err(FsError::MissingInput);
             };
 
-            trace!("wants I/O to rename files");
-            return FsResult::Io(FsIo::Rename(Err(sources)));
+            trace!("wants I/O to rename files ({:?})", self.mode);
+            return FsResult::Io(FsIo::Rename(Err((sources, self.mode))));
         };
 
         debug!("resume after renaming files");
 
         let FsIo::Rename(io) = arg else {
-            let err = FsError::InvalidArgument("rename output", arg);
+            let err = FsError::unexpected("rename output", arg);
             return FsResult::Err(err);
         };
 
         match io {
-            Ok(()) => FsResult::Ok(()),
+            Ok(()) => match self.durable.take() {
+                Some(dirs) => self.sync.insert(SyncPaths::dirs(dirs)).resume(None),
+                None => FsResult::Ok(()),
+            },
             Err(path) => FsResult::Io(FsIo::Rename(Err(path))),
         }
     }
@@ -0,0 +1,52 @@
+//! I/O-free coroutine to set the last modification time of multiple
+//! filesystem files.
+
+use std::{collections::BTreeMap, path::PathBuf, time::SystemTime};
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to set the last modification time of multiple
+/// filesystem files.
+#[derive(Debug)]
+pub struct SetModified {
+    times: Option<BTreeMap<PathBuf, SystemTime>>,
+}
+
+impl SetModified {
+    /// Creates a new coroutine from the given file paths and
+    /// modification times.
+    pub fn new(times: impl IntoIterator<Item = (impl Into<PathBuf>, SystemTime)>) -> Self {
+        let times = times.into_iter().map(|(path, time)| (path.into(), time));
+        let times = Some(times.collect());
+        Self { times }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        let Some(arg) = arg else {
+            let Some(times) = self.times.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to set modification times: {times:?}");
+            return FsResult::Io(FsIo::SetModified(Err(times)));
+        };
+
+        debug!("resume after setting modification times");
+
+        let FsIo::SetModified(io) = arg else {
+            let err = FsError::unexpected("set modified output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(()) => FsResult::Ok(()),
+            Err(times) => FsResult::Io(FsIo::SetModified(Err(times))),
+        }
+    }
+}
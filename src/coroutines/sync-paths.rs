@@ -0,0 +1,99 @@
+//! I/O-free coroutine to flush multiple filesystem files and
+//! directories to disk.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to flush multiple filesystem files and
+/// directories to disk.
+///
+/// Files are synced first using [`FsIo::SyncFile`], then
+/// directories using [`FsIo::SyncDir`], so that directory entries
+/// never become durable before the contents they point to.
+#[derive(Debug)]
+pub struct SyncPaths {
+    queue: VecDeque<FsIo>,
+    wants_io: bool,
+}
+
+impl SyncPaths {
+    /// Creates a new coroutine from the given file and directory
+    /// paths.
+    pub fn new(
+        files: impl IntoIterator<Item = impl Into<PathBuf>>,
+        dirs: impl IntoIterator<Item = impl Into<PathBuf>>,
+    ) -> Self {
+        let files = files.into_iter().map(Into::into).collect::<BTreeSet<_>>();
+        let dirs = dirs.into_iter().map(Into::into).collect::<BTreeSet<_>>();
+
+        let files = files.into_iter().map(|path| FsIo::SyncFile(Err(path)));
+        let dirs = dirs.into_iter().map(|path| FsIo::SyncDir(Err(path)));
+
+        Self {
+            queue: files.chain(dirs).collect(),
+            wants_io: false,
+        }
+    }
+
+    /// Creates a new coroutine flushing the given files, then their
+    /// parent directories.
+    pub fn files(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        let files = paths.into_iter().map(Into::into).collect::<BTreeSet<_>>();
+        let dirs = parents(&files);
+        Self::new(files, dirs)
+    }
+
+    /// Creates a new coroutine flushing the given directories only.
+    pub fn dirs(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self::new(Vec::<PathBuf>::new(), paths)
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        match arg {
+            None if self.wants_io => return FsResult::Err(FsError::MissingInput),
+            None => (),
+            Some(FsIo::SyncFile(Ok(()))) => debug!("resume after syncing file"),
+            Some(FsIo::SyncDir(Ok(()))) => debug!("resume after syncing dir"),
+            Some(FsIo::SyncFile(Err(path))) => return FsResult::Io(FsIo::SyncFile(Err(path))),
+            Some(FsIo::SyncDir(Err(path))) => return FsResult::Io(FsIo::SyncDir(Err(path))),
+            Some(arg) => {
+                let err = FsError::unexpected("sync output", arg);
+                return FsResult::Err(err);
+            }
+        }
+
+        let Some(io) = self.queue.pop_front() else {
+            self.wants_io = false;
+            return FsResult::Ok(());
+        };
+
+        trace!("wants I/O to sync, {} left", self.queue.len());
+        self.wants_io = true;
+        FsResult::Io(io)
+    }
+}
+
+/// Returns the parent directories of the given paths.
+///
+/// The parent of a relative path made of a single component is the
+/// current directory.
+pub(crate) fn parents<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> BTreeSet<PathBuf> {
+    paths
+        .into_iter()
+        .filter_map(|path| path.parent())
+        .map(|dir| match dir {
+            dir if dir == Path::new("") => PathBuf::from("."),
+            dir => dir.to_path_buf(),
+        })
+        .collect()
+}
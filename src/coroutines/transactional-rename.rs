@@ -0,0 +1,197 @@
+//! I/O-free coroutine to rename multiple filesystem files and/or
+//! directories, all or nothing.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace, warn};
+
+use crate::{
+    error::{FsError, FsIoError, FsResult},
+    io::{FsIo, RenameMode},
+};
+
+/// I/O-free coroutine to rename multiple filesystem files and/or
+/// directories, all or nothing.
+///
+/// Renames are applied as if they were simultaneous: cycles and
+/// swaps (`a` → `b`, `b` → `a`) are resolved through temporary
+/// names. Renames are applied one by one using
+/// [`RenameMode::NoReplace`], so destinations must not exist unless
+/// they are renamed by the same transaction.
+///
+/// When a rename fails, already applied renames are undone in
+/// reverse order, then [`FsError::Io`] is returned. If undoing fails
+/// as well, [`FsError::Rollback`] is returned.
+///
+/// In order to roll back, the loop needs to send I/O errors back to
+/// the coroutine (see [`FsIo::Error`]):
+///
+/// ```rust,ignore
+/// let mut arg = None;
+/// let mut coroutine = TransactionalRename::new([("a", "b"), ("b", "a")]);
+///
+/// loop {
+///     match coroutine.resume(arg) {
+///         FsResult::Ok(()) => break,
+///         FsResult::Err(err) => panic!("{err}"),
+///         FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TransactionalRename {
+    renames: Option<Vec<(PathBuf, PathBuf)>>,
+    steps: VecDeque<(PathBuf, PathBuf)>,
+    applied: Vec<(PathBuf, PathBuf)>,
+    current: Option<(PathBuf, PathBuf)>,
+    cause: Option<FsIoError>,
+}
+
+impl TransactionalRename {
+    /// Creates a new coroutine from the given source and destination
+    /// paths.
+    pub fn new(
+        renames: impl IntoIterator<Item = (impl Into<PathBuf>, impl Into<PathBuf>)>,
+    ) -> Self {
+        let renames = renames
+            .into_iter()
+            .map(|(from, to)| (from.into(), to.into()))
+            .collect();
+
+        Self {
+            renames: Some(renames),
+            steps: VecDeque::new(),
+            applied: Vec::new(),
+            current: None,
+            cause: None,
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        match arg {
+            None => {
+                let Some(renames) = self.renames.take() else {
+                    return FsResult::Err(FsError::MissingInput);
+                };
+
+                match plan(renames) {
+                    Ok(steps) => self.steps = steps,
+                    Err(err) => return FsResult::Err(FsError::Io(err)),
+                }
+            }
+            Some(FsIo::Rename(Ok(()))) => {
+                let Some(step) = self.current.take() else {
+                    return FsResult::Err(FsError::MissingInput);
+                };
+
+                if self.cause.is_none() {
+                    debug!("renamed {} to {}", step.0.display(), step.1.display());
+                    self.applied.push(step);
+                } else {
+                    debug!("undid rename to {}", step.0.display());
+                }
+            }
+            Some(FsIo::Rename(Err(input))) => {
+                return FsResult::Io(FsIo::Rename(Err(input)));
+            }
+            Some(FsIo::Error(err)) => {
+                self.current = None;
+
+                match self.cause.take() {
+                    None => {
+                        warn!("rename failed, undo {} renames: {err}", self.applied.len());
+                        self.cause = Some(err);
+                    }
+                    Some(cause) => {
+                        return FsResult::Err(FsError::Rollback(cause, err));
+                    }
+                }
+            }
+            Some(arg) => {
+                let err = FsError::unexpected("rename output", arg);
+                return FsResult::Err(err);
+            }
+        }
+
+        let step = match &self.cause {
+            None => match self.steps.pop_front() {
+                Some(step) => step,
+                None => return FsResult::Ok(()),
+            },
+            Some(cause) => match self.applied.pop() {
+                Some((from, to)) => (to, from),
+                None => return FsResult::Err(FsError::Io(cause.clone())),
+            },
+        };
+
+        trace!(
+            "wants I/O to rename {} to {}",
+            step.0.display(),
+            step.1.display()
+        );
+
+        let input = (vec![step.clone()], RenameMode::NoReplace);
+        self.current = Some(step);
+        FsResult::Io(FsIo::Rename(Err(input)))
+    }
+}
+
+/// Orders the given renames so that they can be applied one by one,
+/// as if they were simultaneous.
+///
+/// A rename is applied once its destination is no longer the source
+/// of a pending rename. When only cycles remain, the source of one
+/// rename is moved to a temporary name first.
+fn plan(renames: Vec<(PathBuf, PathBuf)>) -> Result<VecDeque<(PathBuf, PathBuf)>, FsIoError> {
+    let mut sources = BTreeSet::new();
+    let mut destinations = BTreeSet::new();
+
+    for (from, to) in &renames {
+        if !sources.insert(from) || !destinations.insert(to) {
+            return Err(FsIoError {
+                kind: io::ErrorKind::InvalidInput,
+                message: format!("cannot rename {}: duplicate path", from.display()),
+            });
+        }
+    }
+
+    let mut pending: Vec<_> = renames
+        .into_iter()
+        .filter(|(from, to)| from != to)
+        .collect();
+    let mut steps = VecDeque::with_capacity(pending.len());
+    let mut n = 0;
+
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(_, to)| !pending.iter().any(|(from, _)| from == to));
+
+        if let Some(i) = ready {
+            steps.push_back(pending.remove(i));
+            continue;
+        }
+
+        let (from, to) = pending.remove(0);
+        let tmp = temp_path(&from, n);
+        n += 1;
+
+        steps.push_back((from, tmp.clone()));
+        pending.push((tmp, to));
+    }
+
+    Ok(steps)
+}
+
+fn temp_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".io-fs-{n}.tmp"));
+    path.with_file_name(name)
+}
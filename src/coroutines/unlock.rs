@@ -0,0 +1,50 @@
+//! I/O-free coroutine to release an advisory lock on a filesystem
+//! entry.
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to release an advisory lock on a filesystem
+/// entry, see [`Lock`].
+///
+/// [`Lock`]: crate::coroutines::lock::Lock
+#[derive(Debug)]
+pub struct Unlock {
+    id: Option<u64>,
+}
+
+impl Unlock {
+    /// Creates a new coroutine from the given lock identifier.
+    pub fn new(id: u64) -> Self {
+        let id = Some(id);
+        Self { id }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        let Some(arg) = arg else {
+            let Some(id) = self.id.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to unlock {id}");
+            return FsResult::Io(FsIo::Unlock(Err(id)));
+        };
+
+        debug!("resume after unlocking");
+
+        let FsIo::Unlock(io) = arg else {
+            let err = FsError::unexpected("unlock output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(()) => FsResult::Ok(()),
+            Err(id) => FsResult::Io(FsIo::Unlock(Err(id))),
+        }
+    }
+}
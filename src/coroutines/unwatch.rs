@@ -0,0 +1,48 @@
+//! I/O-free coroutine to stop a filesystem watcher.
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to stop a filesystem watcher, see [`Watch`].
+///
+/// [`Watch`]: crate::coroutines::watch::Watch
+#[derive(Debug)]
+pub struct Unwatch {
+    id: Option<u64>,
+}
+
+impl Unwatch {
+    /// Creates a new coroutine from the given watcher identifier.
+    pub fn new(id: u64) -> Self {
+        let id = Some(id);
+        Self { id }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult {
+        let Some(arg) = arg else {
+            let Some(id) = self.id.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to stop watcher {id}");
+            return FsResult::Io(FsIo::Unwatch(Err(id)));
+        };
+
+        debug!("resume after stopping watcher");
+
+        let FsIo::Unwatch(io) = arg else {
+            let err = FsError::unexpected("unwatch output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(()) => FsResult::Ok(()),
+            Err(id) => FsResult::Io(FsIo::Unwatch(Err(id))),
+        }
+    }
+}
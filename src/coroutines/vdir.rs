@@ -0,0 +1,456 @@
+//! I/O-free coroutines to manage vdir collections.
+//!
+//! A vdir collection is a directory containing one file per item
+//! (contact, event etc), all sharing the same extension (`.vcf`,
+//! `.ics` etc). Collection metadata is stored in plain text files
+//! named after the metadata key, like `displayname` and `color`.
+//!
+//! See <https://vdirsyncer.pimutils.org/en/stable/vdir.html>.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ffi::{OsStr, OsString},
+    fmt, io, mem,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use log::{debug, trace};
+
+use crate::{
+    coroutines::{
+        create_new_file::CreateNewFile, read_dir::ReadDir, read_files::ReadFiles,
+        read_metadata::ReadMetadata, read_metadatas::ReadMetadatas, remove_file::RemoveFile,
+        rename::Rename,
+    },
+    error::{FsError, FsResult},
+    io::{FsIo, FsKind, FsMetadata},
+};
+
+/// The name of the file containing the collection display name.
+pub const DISPLAYNAME: &str = "displayname";
+
+/// The name of the file containing the collection color.
+pub const COLOR: &str = "color";
+
+/// The entity tag of a vdir item, changing whenever the item is
+/// written.
+///
+/// Built from the modification time and the inode number of the
+/// item file, like vdirsyncer does: atomic writes replace the inode,
+/// so that the tag changes even if the modification time does not.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Etag(String);
+
+impl Etag {
+    /// Returns the tag as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&FsMetadata> for Etag {
+    fn from(metadata: &FsMetadata) -> Self {
+        let modified = metadata
+            .modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+
+        let secs = modified.as_secs();
+        let nanos = modified.subsec_nanos();
+        Self(format!("{secs}.{nanos:09};{}", metadata.ino))
+    }
+}
+
+impl fmt::Display for Etag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The metadata of a vdir collection.
+///
+/// Values are stored without trailing whitespace.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CollectionMeta {
+    /// The human-readable name of the collection.
+    pub displayname: Option<String>,
+
+    /// The color of the collection, usually in the `#RRGGBB` form.
+    pub color: Option<String>,
+}
+
+impl CollectionMeta {
+    /// Returns the metadata file names and their value, for values
+    /// that are set.
+    fn files(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        let displayname = self.displayname.as_deref().map(|v| (DISPLAYNAME, v));
+        let color = self.color.as_deref().map(|v| (COLOR, v));
+        displayname.into_iter().chain(color)
+    }
+}
+
+/// Returns `true` if the given item path is hidden, like temporary
+/// files of atomic writes.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+#[derive(Debug)]
+enum ListState {
+    ReadDir(ReadDir),
+    ReadMetadatas(ReadMetadatas),
+}
+
+/// I/O-free coroutine to list items of a vdir collection.
+///
+/// Only regular, non-hidden files with the given extension are
+/// listed.
+///
+/// Returns the etag of each item, by path.
+#[derive(Debug)]
+pub struct ListItems {
+    extension: String,
+    state: ListState,
+}
+
+impl ListItems {
+    /// Creates a new coroutine from the given collection path and
+    /// item extension, without the leading dot (`vcf`, `ics` etc).
+    pub fn new(collection: impl Into<PathBuf>, extension: impl Into<String>) -> Self {
+        Self {
+            extension: extension.into(),
+            state: ListState::ReadDir(ReadDir::new(collection)),
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, Etag>> {
+        loop {
+            match &mut self.state {
+                ListState::ReadDir(coroutine) => {
+                    let paths = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(paths) => paths,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let extension = OsStr::new(&self.extension);
+                    let paths: BTreeSet<_> = paths
+                        .into_iter()
+                        .filter(|path| !is_hidden(path))
+                        .filter(|path| path.extension() == Some(extension))
+                        .collect();
+
+                    if paths.is_empty() {
+                        return FsResult::Ok(BTreeMap::new());
+                    }
+
+                    trace!("read metadata of {} items", paths.len());
+                    self.state = ListState::ReadMetadatas(ReadMetadatas::new(paths));
+                }
+                ListState::ReadMetadatas(coroutine) => {
+                    let metadatas = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(metadatas) => metadatas,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let items = metadatas
+                        .into_iter()
+                        .filter(|(_, metadata)| metadata.kind == FsKind::File)
+                        .map(|(path, metadata)| (path, Etag::from(&metadata)))
+                        .collect();
+
+                    return FsResult::Ok(items);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ReadMetaState {
+    ReadDir(ReadDir),
+    ReadFiles(ReadFiles),
+}
+
+/// I/O-free coroutine to read the metadata of a vdir collection.
+///
+/// The collection is read first, so that missing metadata files are
+/// not requested.
+#[derive(Debug)]
+pub struct ReadMeta {
+    collection: PathBuf,
+    state: ReadMetaState,
+}
+
+impl ReadMeta {
+    /// Creates a new coroutine from the given collection path.
+    pub fn new(collection: impl Into<PathBuf>) -> Self {
+        let collection = collection.into();
+        let state = ReadMetaState::ReadDir(ReadDir::new(&collection));
+        Self { collection, state }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<CollectionMeta> {
+        loop {
+            match &mut self.state {
+                ReadMetaState::ReadDir(coroutine) => {
+                    let paths = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(paths) => paths,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let paths: BTreeSet<_> = [DISPLAYNAME, COLOR]
+                        .into_iter()
+                        .map(|name| self.collection.join(name))
+                        .filter(|path| paths.contains(path))
+                        .collect();
+
+                    if paths.is_empty() {
+                        return FsResult::Ok(CollectionMeta::default());
+                    }
+
+                    self.state = ReadMetaState::ReadFiles(ReadFiles::new(paths));
+                }
+                ReadMetaState::ReadFiles(coroutine) => {
+                    let contents = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(contents) => contents,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let value = |name| {
+                        let contents = contents.get(&self.collection.join(name))?;
+                        let value = String::from_utf8_lossy(contents);
+                        Some(value.trim_end().to_owned())
+                    };
+
+                    let meta = CollectionMeta {
+                        displayname: value(DISPLAYNAME),
+                        color: value(COLOR),
+                    };
+
+                    return FsResult::Ok(meta);
+                }
+            }
+        }
+    }
+}
+
+/// Writes made by the current process, used to build unique
+/// temporary file names.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a temporary file path next to the given path, unique
+/// within the process and among processes, in the form
+/// `.{name}.{pid}.{n}.tmp`.
+fn temp_path(path: &Path) -> PathBuf {
+    let pid = process::id();
+    let n = WRITES.fetch_add(1, Ordering::Relaxed);
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.{n}.tmp"));
+    path.with_file_name(name)
+}
+
+#[derive(Debug)]
+enum WriteMetaState {
+    CreateNewFile(CreateNewFile),
+    Rename(Rename),
+}
+
+/// I/O-free coroutine to write the metadata of a vdir collection.
+///
+/// Only values that are set are written, other metadata files are
+/// left untouched. Each file is written to a new temporary file with
+/// a unique name first, then renamed over the metadata file.
+#[derive(Debug)]
+pub struct WriteMeta {
+    creates: VecDeque<CreateNewFile>,
+    renames: Vec<(PathBuf, PathBuf)>,
+    state: Option<WriteMetaState>,
+}
+
+impl WriteMeta {
+    /// Creates a new coroutine from the given collection path and
+    /// metadata.
+    pub fn new(collection: impl AsRef<Path>, meta: &CollectionMeta) -> Self {
+        let collection = collection.as_ref();
+        let mut creates = VecDeque::new();
+        let mut renames = Vec::new();
+
+        for (name, value) in meta.files() {
+            let path = collection.join(name);
+            let tmp = temp_path(&path);
+            let contents = format!("{value}\n").into_bytes();
+            creates.push_back(CreateNewFile::new(&tmp, contents));
+            renames.push((tmp, path));
+        }
+
+        let state = creates.pop_front().map(WriteMetaState::CreateNewFile);
+
+        Self {
+            creates,
+            renames,
+            state,
+        }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult {
+        loop {
+            let Some(state) = &mut self.state else {
+                debug!("no collection metadata to write");
+                return FsResult::Ok(());
+            };
+
+            match state {
+                WriteMetaState::CreateNewFile(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => match self.creates.pop_front() {
+                        Some(coroutine) => *state = WriteMetaState::CreateNewFile(coroutine),
+                        None => {
+                            let renames = mem::take(&mut self.renames);
+                            *state = WriteMetaState::Rename(Rename::new(renames));
+                        }
+                    },
+                    FsResult::Err(err) => return FsResult::Err(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                WriteMetaState::Rename(coroutine) => return coroutine.resume(arg.take()),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WriteItemState {
+    CreateNewFile(CreateNewFile),
+    ReadMetadata(ReadMetadata),
+    Rename(Rename, Etag),
+    RemoveTemp(RemoveFile, Option<FsError>),
+}
+
+/// I/O-free coroutine to write an item of a vdir collection.
+///
+/// The item is written to a new temporary file with a unique name
+/// first, then renamed over the item file, so that readers never see
+/// a partially written item, and concurrent writers never write to
+/// the same temporary file.
+///
+/// Returns the new etag of the item. It is built from the metadata of
+/// the temporary file before the rename, which keeps both the inode
+/// and the modification time, so that it cannot be the etag of
+/// another writer.
+///
+/// The temporary file is removed when an I/O error occurs, which
+/// requires the loop to send I/O errors back to the coroutine (see
+/// [`FsIo::Error`]).
+#[derive(Debug)]
+pub struct WriteItem {
+    path: PathBuf,
+    tmp: PathBuf,
+    durable: bool,
+    state: WriteItemState,
+}
+
+impl WriteItem {
+    /// Creates a new coroutine from the given item path and raw
+    /// contents.
+    pub fn new(path: impl Into<PathBuf>, contents: impl IntoIterator<Item = u8>) -> Self {
+        let path = path.into();
+        let tmp = temp_path(&path);
+        let coroutine = CreateNewFile::new(&tmp, contents);
+
+        Self {
+            path,
+            tmp,
+            durable: false,
+            state: WriteItemState::CreateNewFile(coroutine),
+        }
+    }
+
+    /// Flushes the item to disk before and after renaming it, see
+    /// [`CreateNewFile::with_durable`] and [`Rename::with_durable`].
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+
+        if let WriteItemState::CreateNewFile(coroutine) = self.state {
+            let coroutine = coroutine.with_durable(durable);
+            self.state = WriteItemState::CreateNewFile(coroutine);
+        }
+
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<Etag> {
+        loop {
+            match &mut self.state {
+                WriteItemState::CreateNewFile(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        let coroutine = ReadMetadata::new(&self.tmp);
+                        self.state = WriteItemState::ReadMetadata(coroutine);
+                    }
+                    // the temporary file belongs to someone else
+                    FsResult::Err(FsError::Io(err)) if err.kind == io::ErrorKind::AlreadyExists => {
+                        return FsResult::Err(FsError::Io(err));
+                    }
+                    FsResult::Err(err) => return self.remove_temp(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                WriteItemState::ReadMetadata(coroutine) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(metadata) => {
+                        let tmp = self.tmp.clone();
+                        let coroutine = Rename::new([(tmp, self.path.clone())]);
+                        let coroutine = coroutine.with_durable(self.durable);
+                        self.state = WriteItemState::Rename(coroutine, Etag::from(&metadata));
+                    }
+                    FsResult::Err(err) => return self.remove_temp(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                WriteItemState::Rename(coroutine, etag) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => return FsResult::Ok(etag.clone()),
+                    FsResult::Err(err) => return self.remove_temp(err),
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+                WriteItemState::RemoveTemp(coroutine, err) => match coroutine.resume(arg.take()) {
+                    FsResult::Ok(()) => {
+                        let err = err.take().unwrap_or(FsError::MissingInput);
+                        return FsResult::Err(err);
+                    }
+                    FsResult::Err(cleanup) => {
+                        debug!(
+                            "cannot remove temporary file {}: {cleanup}",
+                            self.tmp.display()
+                        );
+                        let err = err.take().unwrap_or(FsError::MissingInput);
+                        return FsResult::Err(err);
+                    }
+                    FsResult::Io(io) => return FsResult::Io(io),
+                },
+            }
+        }
+    }
+
+    /// Removes the temporary file after the given I/O error, which is
+    /// returned once the file is removed.
+    fn remove_temp(&mut self, err: FsError) -> FsResult<Etag> {
+        let FsError::Io(_) = err else {
+            return FsResult::Err(err);
+        };
+
+        debug!("remove temporary file {} after error", self.tmp.display());
+        let coroutine = RemoveFile::new(&self.tmp);
+        self.state = WriteItemState::RemoveTemp(coroutine, Some(err));
+        self.resume(None)
+    }
+}
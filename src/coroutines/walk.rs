@@ -0,0 +1,187 @@
+//! I/O-free coroutine to walk a filesystem directory tree.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+    path::PathBuf,
+};
+
+use log::debug;
+
+use crate::{
+    coroutines::{
+        ignore::IgnoreRules, read_dir::ReadDir, read_files::ReadFiles,
+        read_metadatas::ReadMetadatas,
+    },
+    error::FsResult,
+    io::{FsIo, FsKind, FsMetadata},
+};
+
+#[derive(Debug)]
+enum State {
+    ReadDir(ReadDir),
+    IgnoreFiles(ReadFiles),
+    ReadMetadatas(ReadMetadatas),
+}
+
+/// I/O-free coroutine to walk a filesystem directory tree.
+///
+/// Directories are read breadth-first: one [`FsIo::ReadDir`] request
+/// is emitted per directory, followed by one [`FsIo::ReadMetadatas`]
+/// request for all its entries. Symbolic links are not followed.
+///
+/// Entries can be filtered using gitignore-style rules, see
+/// [`Walk::with_ignore_file`] and [`Walk::with_ignore_rules`].
+/// Ignored directories are not read.
+///
+/// Returns the metadata of all entries found under the root
+/// directory, the root directory excluded.
+#[derive(Debug)]
+pub struct Walk {
+    dir: PathBuf,
+    dirs: VecDeque<PathBuf>,
+    paths: BTreeSet<PathBuf>,
+    entries: BTreeMap<PathBuf, FsMetadata>,
+    ignore_files: Vec<String>,
+    rules: IgnoreRules,
+    state: State,
+}
+
+impl Walk {
+    /// Creates a new coroutine from the given root directory path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let dir = path.into();
+
+        Self {
+            state: State::ReadDir(ReadDir::new(&dir)),
+            dir,
+            dirs: VecDeque::new(),
+            paths: BTreeSet::new(),
+            entries: BTreeMap::new(),
+            ignore_files: Vec::new(),
+            rules: IgnoreRules::new(),
+        }
+    }
+
+    /// Reads ignore files with the given name (like
+    /// [`GITIGNORE`]) in every directory walked, then applies their
+    /// rules to the directory entries.
+    ///
+    /// Ignore files are only read if they are listed in their
+    /// directory, so that no extra request is emitted otherwise.
+    ///
+    /// [`GITIGNORE`]: crate::coroutines::ignore::GITIGNORE
+    pub fn with_ignore_file(mut self, name: impl Into<String>) -> Self {
+        self.ignore_files.push(name.into());
+        self
+    }
+
+    /// Applies the given rules, before rules of ignore files.
+    pub fn with_ignore_rules(mut self, rules: IgnoreRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, mut arg: Option<FsIo>) -> FsResult<BTreeMap<PathBuf, FsMetadata>> {
+        loop {
+            match &mut self.state {
+                State::ReadDir(coroutine) => {
+                    let paths = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(paths) => paths,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    let ignore_files: BTreeSet<_> = self
+                        .ignore_files
+                        .iter()
+                        .map(|name| self.dir.join(name))
+                        .filter(|path| paths.contains(path))
+                        .collect();
+
+                    self.paths = paths;
+
+                    if !ignore_files.is_empty() {
+                        debug!("read {} ignore files", ignore_files.len());
+                        self.state = State::IgnoreFiles(ReadFiles::new(ignore_files));
+                        continue;
+                    }
+
+                    if let Some(state) = self.read_metadatas() {
+                        self.state = state;
+                        continue;
+                    }
+                }
+                State::IgnoreFiles(coroutine) => {
+                    let contents = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(contents) => contents,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    for name in &self.ignore_files {
+                        if let Some(contents) = contents.get(&self.dir.join(name)) {
+                            let contents = String::from_utf8_lossy(contents);
+                            self.rules.add(&self.dir, &contents);
+                        }
+                    }
+
+                    if let Some(state) = self.read_metadatas() {
+                        self.state = state;
+                        continue;
+                    }
+                }
+                State::ReadMetadatas(coroutine) => {
+                    let metadatas = match coroutine.resume(arg.take()) {
+                        FsResult::Ok(metadatas) => metadatas,
+                        FsResult::Err(err) => return FsResult::Err(err),
+                        FsResult::Io(io) => return FsResult::Io(io),
+                    };
+
+                    for (path, metadata) in metadatas {
+                        let is_dir = metadata.kind == FsKind::Dir;
+
+                        if self.rules.is_ignored(&path, is_dir) {
+                            debug!("ignore {}", path.display());
+                            continue;
+                        }
+
+                        if is_dir {
+                            self.dirs.push_back(path.clone());
+                        }
+
+                        self.entries.insert(path, metadata);
+                    }
+                }
+            }
+
+            let Some(dir) = self.dirs.pop_front() else {
+                debug!("walked {} entries", self.entries.len());
+                return FsResult::Ok(mem::take(&mut self.entries));
+            };
+
+            self.state = State::ReadDir(ReadDir::new(&dir));
+            self.dir = dir;
+        }
+    }
+
+    /// Returns the state reading metadata of the current directory
+    /// entries, if any.
+    ///
+    /// Entries ignored whatever their kind are filtered out first.
+    fn read_metadatas(&mut self) -> Option<State> {
+        let paths: BTreeSet<_> = mem::take(&mut self.paths)
+            .into_iter()
+            .filter(|path| {
+                !self.rules.is_ignored(path, false) || !self.rules.is_ignored(path, true)
+            })
+            .collect();
+
+        if paths.is_empty() {
+            return None;
+        }
+
+        Some(State::ReadMetadatas(ReadMetadatas::new(paths)))
+    }
+}
@@ -0,0 +1,55 @@
+//! I/O-free coroutine to watch changes under a filesystem directory.
+
+use std::path::PathBuf;
+
+use log::{debug, trace};
+
+use crate::{
+    error::{FsError, FsResult},
+    io::FsIo,
+};
+
+/// I/O-free coroutine to watch changes under a filesystem directory,
+/// recursively.
+///
+/// Returns the identifier of the watcher, to be used with
+/// [`PollEvents`] and [`Unwatch`].
+///
+/// [`PollEvents`]: crate::coroutines::poll_events::PollEvents
+/// [`Unwatch`]: crate::coroutines::unwatch::Unwatch
+#[derive(Debug)]
+pub struct Watch {
+    path: Option<PathBuf>,
+}
+
+impl Watch {
+    /// Creates a new coroutine from the given directory path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = Some(path.into());
+        Self { path }
+    }
+
+    /// Makes the coroutine progress.
+    pub fn resume(&mut self, arg: Option<FsIo>) -> FsResult<u64> {
+        let Some(arg) = arg else {
+            let Some(path) = self.path.take() else {
+                return FsResult::Err(FsError::MissingInput);
+            };
+
+            trace!("wants I/O to watch {}", path.display());
+            return FsResult::Io(FsIo::Watch(Err(path)));
+        };
+
+        debug!("resume after watching directory");
+
+        let FsIo::Watch(io) = arg else {
+            let err = FsError::unexpected("watch output", arg);
+            return FsResult::Err(err);
+        };
+
+        match io {
+            Ok(id) => FsResult::Ok(id),
+            Err(path) => FsResult::Io(FsIo::Watch(Err(path))),
+        }
+    }
+}
@@ -1,13 +1,18 @@
 //! Filesystem coroutines errors.
 
+use std::{borrow::Cow, io};
+
 use thiserror::Error;
 
 use crate::io::FsIo;
 
 /// Errors that can occur during any filesystem coroutine progression.
 ///
-/// Only coroutine misuses should lead to these error variants.
+/// Apart from [`FsError::Io`] and [`FsError::Rollback`], emitted by
+/// coroutines receiving I/O errors (see [`FsIo::Error`]), only
+/// coroutine misuses should lead to these error variants.
 #[derive(Clone, Debug, Error)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FsError {
     /// The coroutine input is missing or has already been used.
     ///
@@ -23,11 +28,43 @@ pub enum FsError {
     /// another coroutine, which should not happen if the runtime maps
     /// correctly the arguments.
     #[error("Invalid argument: expected {0}, got {1:?}")]
-    InvalidArgument(&'static str, FsIo),
+    InvalidArgument(Cow<'static, str>, FsIo),
+
+    /// The runtime failed to process an I/O request.
+    ///
+    /// Occurs when the coroutine receives an I/O error it cannot
+    /// recover from. Changes already applied by the coroutine have
+    /// been undone, if the coroutine supports it.
+    #[error("I/O error: {0}")]
+    Io(FsIoError),
+
+    /// The runtime failed to process an I/O request, then failed
+    /// again while the coroutine was undoing its changes.
+    ///
+    /// The first error is the original one, the second one is the
+    /// rollback error. The filesystem may be left in an intermediate
+    /// state.
+    #[error("I/O error: {0}, then rollback error: {1}")]
+    Rollback(FsIoError, FsIoError),
+}
+
+impl FsError {
+    /// Builds the error of a coroutine receiving the given unexpected
+    /// I/O response.
+    ///
+    /// I/O errors sent back by loops lead to [`FsError::Io`], other
+    /// responses lead to [`FsError::InvalidArgument`].
+    pub(crate) fn unexpected(expected: &'static str, arg: FsIo) -> Self {
+        match arg {
+            FsIo::Error(err) => Self::Io(err),
+            arg => Self::InvalidArgument(expected.into(), arg),
+        }
+    }
 }
 
 /// Output emitted after a coroutine finishes its progression.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FsResult<T = ()> {
     /// The coroutine has successfully terminated its progression.
     Ok(T),
@@ -39,3 +76,109 @@ pub enum FsResult<T = ()> {
     /// progress.
     Io(FsIo),
 }
+
+/// Clonable representation of an I/O error returned by a runtime.
+///
+/// Unlike [`io::Error`], this error can be stored, compared and sent
+/// back later on, which is useful for runtimes replaying I/O.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[error("{message}")]
+pub struct FsIoError {
+    /// The kind of the original I/O error.
+    #[cfg_attr(feature = "serde", serde(with = "error_kind"))]
+    pub kind: io::ErrorKind,
+
+    /// The message of the original I/O error.
+    pub message: String,
+}
+
+impl From<&io::Error> for FsIoError {
+    fn from(err: &io::Error) -> Self {
+        Self {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for FsIoError {
+    fn from(err: io::Error) -> Self {
+        Self::from(&err)
+    }
+}
+
+impl From<FsIoError> for io::Error {
+    fn from(err: FsIoError) -> Self {
+        io::Error::new(err.kind, err.message)
+    }
+}
+
+/// Serde (de)serializer for [`io::ErrorKind`], which does not
+/// implement serde traits.
+///
+/// Kinds are serialized using their variant name. Unknown kinds are
+/// deserialized as [`io::ErrorKind::Other`].
+#[cfg(feature = "serde")]
+mod error_kind {
+    use std::io::ErrorKind;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const KINDS: &[ErrorKind] = &[
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::ConnectionRefused,
+        ErrorKind::ConnectionReset,
+        ErrorKind::HostUnreachable,
+        ErrorKind::NetworkUnreachable,
+        ErrorKind::ConnectionAborted,
+        ErrorKind::NotConnected,
+        ErrorKind::AddrInUse,
+        ErrorKind::AddrNotAvailable,
+        ErrorKind::NetworkDown,
+        ErrorKind::BrokenPipe,
+        ErrorKind::AlreadyExists,
+        ErrorKind::WouldBlock,
+        ErrorKind::NotADirectory,
+        ErrorKind::IsADirectory,
+        ErrorKind::DirectoryNotEmpty,
+        ErrorKind::ReadOnlyFilesystem,
+        ErrorKind::StaleNetworkFileHandle,
+        ErrorKind::InvalidInput,
+        ErrorKind::InvalidData,
+        ErrorKind::TimedOut,
+        ErrorKind::WriteZero,
+        ErrorKind::StorageFull,
+        ErrorKind::NotSeekable,
+        ErrorKind::FileTooLarge,
+        ErrorKind::ResourceBusy,
+        ErrorKind::ExecutableFileBusy,
+        ErrorKind::Deadlock,
+        ErrorKind::CrossesDevices,
+        ErrorKind::TooManyLinks,
+        ErrorKind::InvalidFilename,
+        ErrorKind::ArgumentListTooLong,
+        ErrorKind::Interrupted,
+        ErrorKind::Unsupported,
+        ErrorKind::UnexpectedEof,
+        ErrorKind::OutOfMemory,
+        ErrorKind::Other,
+    ];
+
+    pub fn serialize<S: Serializer>(kind: &ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{kind:?}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ErrorKind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        let kind = KINDS
+            .iter()
+            .find(|kind| format!("{kind:?}") == name)
+            .copied()
+            .unwrap_or(ErrorKind::Other);
+
+        Ok(kind)
+    }
+}
@@ -1,11 +1,14 @@
 //! Filesystem I/O requests and responses.
 
 use std::{
-    collections::{HashMap, HashSet},
-    fmt,
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
     path::PathBuf,
+    time::{Duration, SystemTime},
 };
 
+use crate::error::FsIoError;
+
 /// The filesystem I/O request and response enum, emitted by
 /// [coroutines] and processed by [runtimes].
 ///
@@ -13,10 +16,29 @@ use std::{
 /// coroutine can emit. Runtimes should be able to handle all
 /// variants.
 ///
+/// Batch inputs and outputs are ordered by path, so that runtimes
+/// process batch requests in a defined order, and so that outputs
+/// can be iterated deterministically.
+///
 /// [coroutines]: crate::coroutines
 /// [runtimes]: crate::runtimes
-#[derive(Clone)]
+/// [`FsError::Io`]: crate::error::FsError::Io
+#[derive(Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FsIo {
+    /// I/O request to append raw contents to a filesystem file.
+    ///
+    /// The file is created if it does not exist. Contents are
+    /// written with a single append (`O_APPEND`) write, so that
+    /// concurrent appends are not interleaved on local filesystems.
+    /// A short write fails instead of being continued, in which case
+    /// the file may end with part of the contents.
+    ///
+    /// Input: tuple of file path and raw contents (bytes)
+    ///
+    /// Output: none
+    AppendFile(Result<(), (PathBuf, Vec<u8>)>),
+
     /// I/O request to create a filesystem directory.
     ///
     /// Input: directory path
@@ -26,10 +48,13 @@ pub enum FsIo {
 
     /// I/O request to create multiple filesystem directories.
     ///
+    /// Parent directories are created before their children, see
+    /// [`sort_by_depth`](crate::runtimes::sort_by_depth).
+    ///
     /// Input: set of directory paths
     ///
     /// Output: none
-    CreateDirs(Result<(), HashSet<PathBuf>>),
+    CreateDirs(Result<(), BTreeSet<PathBuf>>),
 
     /// I/O request to create a filesystem file.
     ///
@@ -43,14 +68,71 @@ pub enum FsIo {
     /// Input: map of path and raw contents (bytes)
     ///
     /// Output: none
-    CreateFiles(Result<(), HashMap<PathBuf, Vec<u8>>>),
+    CreateFiles(Result<(), BTreeMap<PathBuf, Vec<u8>>>),
+
+    /// I/O request to create a filesystem file, failing with
+    /// [`std::io::ErrorKind::AlreadyExists`] if it already exists.
+    ///
+    /// The existence check and the creation are atomic (`O_EXCL`).
+    ///
+    /// Input: tuple of file path and raw contents (bytes)
+    ///
+    /// Output: none
+    CreateNewFile(Result<(), (PathBuf, Vec<u8>)>),
+
+    /// I/O response to a request that the runtime failed to process.
+    ///
+    /// Runtimes return I/O errors on their own, loops can send them
+    /// back to coroutines using this variant, for example with
+    /// `FsIo::from(err)`. Coroutines able to recover from I/O errors
+    /// (rollback, retry, missing entries etc) then get a chance to,
+    /// other coroutines fail with [`FsError::Io`].
+    ///
+    /// This variant is not a request: runtimes reject it with
+    /// [`io::ErrorKind::InvalidInput`].
+    ///
+    /// Output: the I/O error
+    Error(FsIoError),
+
+    /// I/O request to acquire an advisory lock on a filesystem entry.
+    ///
+    /// The lock is held by the runtime until it is released with
+    /// [`FsIo::Unlock`]. Each lock has its own owner: locking an
+    /// entry already locked by the same runtime conflicts with the
+    /// existing lock, like locking it from another process would.
+    ///
+    /// Input: tuple of entry path and lock mode
+    ///
+    /// Output: lock identifier
+    Lock(Result<u64, (PathBuf, LockMode)>),
+
+    /// I/O request to poll events of a filesystem watcher, see
+    /// [`FsIo::Watch`].
+    ///
+    /// Waits up to the given timeout for events to occur, then
+    /// returns all pending events.
+    ///
+    /// Input: tuple of watcher identifier and timeout
+    ///
+    /// Output: list of events, empty if the timeout elapsed
+    PollEvents(Result<Vec<FsEvent>, (u64, Duration)>),
+
+    /// I/O request to read chunks of multiple filesystem files.
+    ///
+    /// A chunk shorter than the requested length means that the end
+    /// of the file has been reached.
+    ///
+    /// Input: map of path and chunk to read
+    ///
+    /// Output: map of path and raw chunk (bytes)
+    ReadChunks(Result<BTreeMap<PathBuf, Vec<u8>>, BTreeMap<PathBuf, FsChunk>>),
 
     /// I/O request to read entries from a filesystem directory.
     ///
     /// Input: directory path
     ///
     /// Output: set of entry paths
-    ReadDir(Result<HashSet<PathBuf>, PathBuf>),
+    ReadDir(Result<BTreeSet<PathBuf>, PathBuf>),
 
     /// I/O request to read a filesystem file.
     ///
@@ -64,7 +146,31 @@ pub enum FsIo {
     /// Input: set of file paths
     ///
     /// Output: map of path and raw contents (bytes)
-    ReadFiles(Result<HashMap<PathBuf, Vec<u8>>, HashSet<PathBuf>>),
+    ReadFiles(Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>),
+
+    /// I/O request to read metadata of a filesystem entry, without
+    /// following symbolic links.
+    ///
+    /// Input: entry path
+    ///
+    /// Output: entry metadata
+    ReadMetadata(Result<FsMetadata, PathBuf>),
+
+    /// I/O request to read metadata of multiple filesystem entries,
+    /// without following symbolic links.
+    ///
+    /// Input: set of entry paths
+    ///
+    /// Output: map of path and entry metadata
+    ReadMetadatas(Result<BTreeMap<PathBuf, FsMetadata>, BTreeSet<PathBuf>>),
+
+    /// I/O request to read metadata of a filesystem entry, following
+    /// symbolic links.
+    ///
+    /// Input: entry path
+    ///
+    /// Output: metadata of the entry, or of the entry it links to
+    ReadTargetMetadata(Result<FsMetadata, PathBuf>),
 
     /// I/O request to remove a filesystem directory.
     ///
@@ -75,10 +181,13 @@ pub enum FsIo {
 
     /// I/O request to remove multiple filesystem directories.
     ///
+    /// Child directories are removed before their parents, see
+    /// [`sort_by_depth`](crate::runtimes::sort_by_depth).
+    ///
     /// Input: set of directory paths
     ///
     /// Output: none
-    RemoveDirs(Result<(), HashSet<PathBuf>>),
+    RemoveDirs(Result<(), BTreeSet<PathBuf>>),
 
     /// I/O request to remove a filesystem file.
     ///
@@ -92,20 +201,115 @@ pub enum FsIo {
     /// Input: set of file paths
     ///
     /// Output: none
-    RemoveFiles(Result<(), HashSet<PathBuf>>),
+    RemoveFiles(Result<(), BTreeSet<PathBuf>>),
 
     /// I/O request to rename multiple filesystem files and/or
     /// directories.
     ///
-    /// Input: set of directory and/or file paths
+    /// Input: tuple of source and destination paths, and rename mode
+    ///
+    /// Output: none
+    Rename(Result<(), (Vec<(PathBuf, PathBuf)>, RenameMode)>),
+
+    /// I/O request to set the last modification time of multiple
+    /// filesystem files.
+    ///
+    /// Input: map of file paths and modification times
     ///
     /// Output: none
-    Rename(Result<(), Vec<(PathBuf, PathBuf)>>),
+    SetModified(Result<(), BTreeMap<PathBuf, SystemTime>>),
+
+    /// I/O request to flush entries of a filesystem directory to
+    /// disk.
+    ///
+    /// Makes the creation, removal and renaming of the directory
+    /// entries durable. A no-op on platforms where directories
+    /// cannot be synced.
+    ///
+    /// Input: directory path
+    ///
+    /// Output: none
+    SyncDir(Result<(), PathBuf>),
+
+    /// I/O request to flush contents and metadata of a filesystem
+    /// file to disk (`fsync`).
+    ///
+    /// Input: file path
+    ///
+    /// Output: none
+    SyncFile(Result<(), PathBuf>),
+
+    /// I/O request to release an advisory lock, see [`FsIo::Lock`].
+    ///
+    /// Input: lock identifier
+    ///
+    /// Output: none
+    Unlock(Result<(), u64>),
+
+    /// I/O request to stop a filesystem watcher, see [`FsIo::Watch`].
+    ///
+    /// Input: watcher identifier
+    ///
+    /// Output: none
+    Unwatch(Result<(), u64>),
+
+    /// I/O request to watch changes of entries under a filesystem
+    /// directory, recursively.
+    ///
+    /// Events are queued by the runtime until they are polled with
+    /// [`FsIo::PollEvents`], and until the watcher is stopped with
+    /// [`FsIo::Unwatch`].
+    ///
+    /// Input: directory path
+    ///
+    /// Output: watcher identifier
+    Watch(Result<u64, PathBuf>),
+}
+
+impl FsIo {
+    /// Returns `true` if the I/O request mutates the filesystem.
+    ///
+    /// Read requests like [`FsIo::ReadDir`] return `false`, whereas
+    /// create, remove and rename requests return `true`.
+    pub fn is_mutation(&self) -> bool {
+        match self {
+            Self::Error(_)
+            | Self::Lock(_)
+            | Self::PollEvents(_)
+            | Self::ReadChunks(_)
+            | Self::ReadDir(_)
+            | Self::ReadFile(_)
+            | Self::ReadFiles(_)
+            | Self::ReadMetadata(_)
+            | Self::ReadMetadatas(_)
+            | Self::ReadTargetMetadata(_)
+            | Self::SyncDir(_)
+            | Self::SyncFile(_)
+            | Self::Unlock(_)
+            | Self::Unwatch(_)
+            | Self::Watch(_) => false,
+            Self::AppendFile(_)
+            | Self::CreateDir(_)
+            | Self::CreateDirs(_)
+            | Self::CreateFile(_)
+            | Self::CreateFiles(_)
+            | Self::CreateNewFile(_)
+            | Self::RemoveDir(_)
+            | Self::RemoveDirs(_)
+            | Self::RemoveFile(_)
+            | Self::RemoveFiles(_)
+            | Self::Rename(_)
+            | Self::SetModified(_) => true,
+        }
+    }
 }
 
 impl fmt::Debug for FsIo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::AppendFile(Ok(_)) => f.write_str("append file output"),
+            Self::AppendFile(Err(_)) => f.write_str("append file input"),
+
             Self::CreateDir(Ok(_)) => f.write_str("create dir output"),
             Self::CreateDir(Err(_)) => f.write_str("create dir input"),
 
@@ -118,6 +322,20 @@ impl fmt::Debug for FsIo {
             Self::CreateFiles(Ok(_)) => f.write_str("create files output"),
             Self::CreateFiles(Err(_)) => f.write_str("create files input"),
 
+            Self::CreateNewFile(Ok(_)) => f.write_str("create new file output"),
+            Self::CreateNewFile(Err(_)) => f.write_str("create new file input"),
+
+            Self::Error(err) => write!(f, "error output: {err}"),
+
+            Self::Lock(Ok(_)) => f.write_str("lock output"),
+            Self::Lock(Err(_)) => f.write_str("lock input"),
+
+            Self::PollEvents(Ok(_)) => f.write_str("poll events output"),
+            Self::PollEvents(Err(_)) => f.write_str("poll events input"),
+
+            Self::ReadChunks(Ok(_)) => f.write_str("read chunks output"),
+            Self::ReadChunks(Err(_)) => f.write_str("read chunks input"),
+
             Self::ReadDir(Ok(_)) => f.write_str("read dir output"),
             Self::ReadDir(Err(_)) => f.write_str("read dir input"),
 
@@ -127,6 +345,15 @@ impl fmt::Debug for FsIo {
             Self::ReadFiles(Ok(_)) => f.write_str("read files output"),
             Self::ReadFiles(Err(_)) => f.write_str("read files input"),
 
+            Self::ReadMetadata(Ok(_)) => f.write_str("read metadata output"),
+            Self::ReadMetadata(Err(_)) => f.write_str("read metadata input"),
+
+            Self::ReadMetadatas(Ok(_)) => f.write_str("read metadatas output"),
+            Self::ReadMetadatas(Err(_)) => f.write_str("read metadatas input"),
+
+            Self::ReadTargetMetadata(Ok(_)) => f.write_str("read target metadata output"),
+            Self::ReadTargetMetadata(Err(_)) => f.write_str("read target metadata input"),
+
             Self::RemoveDir(Ok(_)) => f.write_str("remove dir output"),
             Self::RemoveDir(Err(_)) => f.write_str("remove dir input"),
 
@@ -141,6 +368,202 @@ impl fmt::Debug for FsIo {
 
             Self::Rename(Ok(_)) => f.write_str("rename output"),
             Self::Rename(Err(_)) => f.write_str("rename input"),
+
+            Self::SetModified(Ok(_)) => f.write_str("set modified output"),
+            Self::SetModified(Err(_)) => f.write_str("set modified input"),
+
+            Self::SyncDir(Ok(_)) => f.write_str("sync dir output"),
+            Self::SyncDir(Err(_)) => f.write_str("sync dir input"),
+
+            Self::SyncFile(Ok(_)) => f.write_str("sync file output"),
+            Self::SyncFile(Err(_)) => f.write_str("sync file input"),
+
+            Self::Unlock(Ok(_)) => f.write_str("unlock output"),
+            Self::Unlock(Err(_)) => f.write_str("unlock input"),
+
+            Self::Unwatch(Ok(_)) => f.write_str("unwatch output"),
+            Self::Unwatch(Err(_)) => f.write_str("unwatch input"),
+
+            Self::Watch(Ok(_)) => f.write_str("watch output"),
+            Self::Watch(Err(_)) => f.write_str("watch input"),
+        }
+    }
+}
+
+impl From<FsIoError> for FsIo {
+    fn from(err: FsIoError) -> Self {
+        Self::Error(err)
+    }
+}
+
+impl From<io::Error> for FsIo {
+    fn from(err: io::Error) -> Self {
+        Self::Error(err.into())
+    }
+}
+
+/// The way [`FsIo::Rename`] behaves when the destination exists.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenameMode {
+    /// Silently replace the destination, like [`fs::rename`].
+    #[default]
+    Replace,
+
+    /// Fail with [`std::io::ErrorKind::AlreadyExists`] if the
+    /// destination exists.
+    ///
+    /// Atomic on Linux (via `renameat2`) when the filesystem
+    /// supports it, otherwise the destination is checked right
+    /// before renaming.
+    NoReplace,
+
+    /// Atomically exchange the source and the destination, which
+    /// must both exist.
+    ///
+    /// Only supported on Linux (via `renameat2`), other platforms
+    /// fail with [`std::io::ErrorKind::Unsupported`].
+    Exchange,
+}
+
+/// The mode of an advisory lock, see [`FsIo::Lock`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LockMode {
+    /// Wait for a shared lock, which can be held by multiple owners
+    /// at once.
+    Shared,
+
+    /// Wait for an exclusive lock, which can only be held by a
+    /// single owner.
+    #[default]
+    Exclusive,
+
+    /// Try to acquire a shared lock, failing with
+    /// [`std::io::ErrorKind::WouldBlock`] if an exclusive lock is
+    /// held.
+    TryShared,
+
+    /// Try to acquire an exclusive lock, failing with
+    /// [`std::io::ErrorKind::WouldBlock`] if any lock is held.
+    TryExclusive,
+}
+
+/// A range of bytes to read from a filesystem file, see
+/// [`FsIo::ReadChunks`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FsChunk {
+    /// The position of the first byte to read.
+    pub offset: u64,
+
+    /// The maximum number of bytes to read.
+    pub len: u64,
+}
+
+/// A change that occurred under a watched filesystem directory, see
+/// [`FsIo::Watch`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FsEvent {
+    /// An entry was created, or moved from outside the watched
+    /// directory.
+    Created(PathBuf),
+
+    /// The contents of a file were modified.
+    Modified(PathBuf),
+
+    /// An entry was removed, or moved outside the watched directory.
+    Removed(PathBuf),
+
+    /// An entry was renamed within the watched directory, from the
+    /// first path to the second one.
+    Renamed(PathBuf, PathBuf),
+
+    /// Some events were lost because the queue of the watcher
+    /// overflowed: the watched directory should be scanned again.
+    Overflowed,
+}
+
+/// The kind of a filesystem entry.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FsKind {
+    /// The entry is a regular file.
+    File,
+
+    /// The entry is a directory.
+    Dir,
+
+    /// The entry is a symbolic link.
+    Symlink,
+
+    /// The entry is something else (socket, device, FIFO etc).
+    Other,
+}
+
+/// The metadata of a filesystem entry.
+///
+/// Represents the subset of [`fs::Metadata`] that coroutines need,
+/// in a form that runtimes can build and send back.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FsMetadata {
+    /// The kind of the entry.
+    pub kind: FsKind,
+
+    /// The size of the entry, in bytes.
+    pub len: u64,
+
+    /// The last modification time of the entry, if available.
+    pub modified: Option<SystemTime>,
+
+    /// The identifier of the device containing the entry.
+    ///
+    /// Always 0 on non-Unix platforms.
+    pub dev: u64,
+
+    /// The inode number of the entry.
+    ///
+    /// Always 0 on non-Unix platforms.
+    pub ino: u64,
+
+    /// The number of hard links pointing to the entry.
+    ///
+    /// Always 1 on non-Unix platforms.
+    pub nlink: u64,
+}
+
+impl From<fs::Metadata> for FsMetadata {
+    fn from(metadata: fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+
+        let kind = if file_type.is_symlink() {
+            FsKind::Symlink
+        } else if file_type.is_dir() {
+            FsKind::Dir
+        } else if file_type.is_file() {
+            FsKind::File
+        } else {
+            FsKind::Other
+        };
+
+        #[cfg(unix)]
+        let (dev, ino, nlink) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino(), metadata.nlink())
+        };
+
+        #[cfg(not(unix))]
+        let (dev, ino, nlink) = (0, 0, 1);
+
+        Self {
+            kind,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            dev,
+            ino,
+            nlink,
         }
     }
 }
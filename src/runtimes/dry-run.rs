@@ -0,0 +1,224 @@
+//! The dry-run filesystem runtime wrapper.
+//!
+//! This module wraps another runtime handler: read requests are
+//! processed by the wrapped handler, whereas mutating requests are
+//! only recorded into a plan and reported as succeeded. The plan can
+//! then be displayed before actually applying changes.
+//!
+//! ```rust,ignore
+//! use io_fs::runtimes::{dry_run::DryRun, std::handle};
+//!
+//! let mut dry_run = DryRun::new();
+//!
+//! // blocking runtimes can be wrapped directly
+//! let output = dry_run.handle(io, handle)?;
+//!
+//! // async runtimes need to record the request first
+//! let output = match dry_run.record(io) {
+//!     Ok(output) => output,
+//!     Err(io) => io_fs::runtimes::tokio::handle(io).await?,
+//! };
+//!
+//! for mutation in dry_run.plan() {
+//!     println!("would {mutation}");
+//! }
+//! ```
+
+use std::{fmt, io, path::PathBuf, time::SystemTime};
+
+use log::debug;
+
+use crate::{
+    io::{FsIo, RenameMode},
+    runtimes::sort_by_depth,
+};
+
+/// A filesystem mutation recorded by the [`DryRun`] runtime.
+///
+/// Batch requests are flattened, so that one mutation always
+/// concerns one path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FsMutation {
+    /// The given raw contents would have been appended to the given
+    /// file.
+    AppendFile(PathBuf, Vec<u8>),
+
+    /// The given directory would have been created.
+    CreateDir(PathBuf),
+
+    /// The given file would have been created with the given raw
+    /// contents.
+    CreateFile(PathBuf, Vec<u8>),
+
+    /// The given directory would have been removed, recursively.
+    RemoveDir(PathBuf),
+
+    /// The given file would have been removed.
+    RemoveFile(PathBuf),
+
+    /// The first path would have been renamed to the second one.
+    Rename(PathBuf, PathBuf),
+
+    /// Both paths would have been exchanged.
+    Exchange(PathBuf, PathBuf),
+
+    /// The last modification time of the given file would have been
+    /// set to the given time.
+    SetModified(PathBuf, SystemTime),
+}
+
+impl fmt::Display for FsMutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AppendFile(path, contents) => {
+                let n = contents.len();
+                write!(f, "append {n} bytes to file {}", path.display())
+            }
+            Self::CreateDir(path) => {
+                write!(f, "create directory {}", path.display())
+            }
+            Self::CreateFile(path, contents) => {
+                let n = contents.len();
+                write!(f, "create file {} ({n} bytes)", path.display())
+            }
+            Self::RemoveDir(path) => {
+                write!(f, "remove directory {}", path.display())
+            }
+            Self::RemoveFile(path) => {
+                write!(f, "remove file {}", path.display())
+            }
+            Self::Rename(from, to) => {
+                write!(f, "rename {} to {}", from.display(), to.display())
+            }
+            Self::Exchange(a, b) => {
+                write!(f, "exchange {} and {}", a.display(), b.display())
+            }
+            Self::SetModified(path, _) => {
+                write!(f, "set modification time of file {}", path.display())
+            }
+        }
+    }
+}
+
+/// The dry-run filesystem runtime.
+///
+/// Holds the plan of mutations recorded so far.
+#[derive(Clone, Debug, Default)]
+pub struct DryRun {
+    plan: Vec<FsMutation>,
+}
+
+impl DryRun {
+    /// Creates a new dry-run runtime with an empty plan.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the mutations recorded so far, in order.
+    pub fn plan(&self) -> &[FsMutation] {
+        &self.plan
+    }
+
+    /// Consumes the runtime and returns the recorded mutations.
+    pub fn into_plan(self) -> Vec<FsMutation> {
+        self.plan
+    }
+
+    /// Records the given I/O request if it mutates the filesystem.
+    ///
+    /// Returns the successful I/O response matching the recorded
+    /// request, or gives back the request if it needs to be
+    /// processed by a real runtime.
+    pub fn record(&mut self, input: FsIo) -> Result<FsIo, FsIo> {
+        let output = match input {
+            FsIo::AppendFile(Err((path, contents))) => {
+                self.plan.push(FsMutation::AppendFile(path, contents));
+                FsIo::AppendFile(Ok(()))
+            }
+            FsIo::CreateDir(Err(path)) => {
+                self.plan.push(FsMutation::CreateDir(path));
+                FsIo::CreateDir(Ok(()))
+            }
+            FsIo::CreateDirs(Err(paths)) => {
+                let mutations = sort_by_depth(paths).into_iter().map(FsMutation::CreateDir);
+                self.plan.extend(mutations);
+                FsIo::CreateDirs(Ok(()))
+            }
+            FsIo::CreateFile(Err((path, contents))) => {
+                self.plan.push(FsMutation::CreateFile(path, contents));
+                FsIo::CreateFile(Ok(()))
+            }
+            FsIo::CreateFiles(Err(contents)) => {
+                let mutations = contents
+                    .into_iter()
+                    .map(|(path, contents)| FsMutation::CreateFile(path, contents));
+                self.plan.extend(mutations);
+                FsIo::CreateFiles(Ok(()))
+            }
+            FsIo::CreateNewFile(Err((path, contents))) => {
+                self.plan.push(FsMutation::CreateFile(path, contents));
+                FsIo::CreateNewFile(Ok(()))
+            }
+            FsIo::RemoveDir(Err(path)) => {
+                self.plan.push(FsMutation::RemoveDir(path));
+                FsIo::RemoveDir(Ok(()))
+            }
+            FsIo::RemoveDirs(Err(paths)) => {
+                let paths = sort_by_depth(paths).into_iter().rev();
+                let mutations = paths.map(FsMutation::RemoveDir);
+                self.plan.extend(mutations);
+                FsIo::RemoveDirs(Ok(()))
+            }
+            FsIo::RemoveFile(Err(path)) => {
+                self.plan.push(FsMutation::RemoveFile(path));
+                FsIo::RemoveFile(Ok(()))
+            }
+            FsIo::RemoveFiles(Err(paths)) => {
+                let mutations = paths.into_iter().map(FsMutation::RemoveFile);
+                self.plan.extend(mutations);
+                FsIo::RemoveFiles(Ok(()))
+            }
+            FsIo::Rename(Err((paths, mode))) => {
+                let mutations = paths.into_iter().map(|(from, to)| match mode {
+                    RenameMode::Exchange => FsMutation::Exchange(from, to),
+                    _ => FsMutation::Rename(from, to),
+                });
+                self.plan.extend(mutations);
+                FsIo::Rename(Ok(()))
+            }
+            FsIo::SetModified(Err(times)) => {
+                let mutations = times
+                    .into_iter()
+                    .map(|(path, time)| FsMutation::SetModified(path, time));
+                self.plan.extend(mutations);
+                FsIo::SetModified(Ok(()))
+            }
+            // entries may only exist in the plan, so there is
+            // nothing to flush
+            FsIo::SyncDir(Err(_)) => FsIo::SyncDir(Ok(())),
+            FsIo::SyncFile(Err(_)) => FsIo::SyncFile(Ok(())),
+            input => return Err(input),
+        };
+
+        debug!("record {output:?} from dry-run runtime");
+
+        Ok(output)
+    }
+
+    /// The dry-run filesystem runtime handler.
+    ///
+    /// Mutating requests are recorded (see [`DryRun::record`]),
+    /// other requests are forwarded to the given blocking runtime
+    /// `handler`.
+    pub fn handle(
+        &mut self,
+        input: FsIo,
+        handler: impl FnOnce(FsIo) -> io::Result<FsIo>,
+    ) -> io::Result<FsIo> {
+        match self.record(input) {
+            Ok(output) => Ok(output),
+            Err(input) => handler(input),
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Inotify-based filesystem watchers, shared by runtimes.
+//!
+//! Watchers are kept in a process-wide registry indexed by
+//! identifier, so that runtime handlers can remain plain functions.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{CString, OsStr},
+    fs, io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use log::{debug, warn};
+
+use crate::io::FsEvent;
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_MODIFY
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ONLYDIR;
+
+static WATCHERS: Mutex<BTreeMap<u64, Arc<Mutex<Watcher>>>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Starts watching the given directory, recursively.
+///
+/// Returns the identifier of the new watcher.
+pub fn watch(path: &Path) -> io::Result<u64> {
+    let watcher = Watcher::new(path)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    debug!("watch {} with watcher {id}", path.display());
+    watchers().insert(id, Arc::new(Mutex::new(watcher)));
+
+    Ok(id)
+}
+
+/// Waits up to the given timeout for events of the given watcher,
+/// then returns all pending events.
+///
+/// Only the given watcher is locked while waiting, other watchers
+/// can be polled concurrently.
+pub fn poll_events(id: u64, timeout: Duration) -> io::Result<Vec<FsEvent>> {
+    let watcher = match watchers().get(&id) {
+        Some(watcher) => watcher.clone(),
+        None => return Err(not_found(id)),
+    };
+
+    let mut watcher = watcher.lock().unwrap_or_else(|err| err.into_inner());
+    watcher.wait(timeout)?;
+    watcher.read_events()
+}
+
+/// Stops the given watcher.
+pub fn unwatch(id: u64) -> io::Result<()> {
+    match watchers().remove(&id) {
+        Some(_) => Ok(()),
+        None => Err(not_found(id)),
+    }
+}
+
+fn watchers() -> MutexGuard<'static, BTreeMap<u64, Arc<Mutex<Watcher>>>> {
+    WATCHERS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn not_found(id: u64) -> io::Error {
+    let kind = io::ErrorKind::NotFound;
+    io::Error::new(kind, format!("cannot find watcher {id}"))
+}
+
+/// An inotify instance watching a directory tree.
+///
+/// Inotify watches are not recursive, so one watch is added per
+/// directory, including directories created after the watcher.
+struct Watcher {
+    fd: OwnedFd,
+    dirs: BTreeMap<i32, PathBuf>,
+}
+
+impl Watcher {
+    fn new(root: &Path) -> io::Result<Self> {
+        // SAFETY: no pointer involved
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the file descriptor has just been opened
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut watcher = Self {
+            fd,
+            dirs: BTreeMap::new(),
+        };
+
+        watcher.add_tree(root, None)?;
+
+        Ok(watcher)
+    }
+
+    /// Watches the given directory and its children.
+    ///
+    /// Entries found while walking the tree are reported as created
+    /// to the given events, if any.
+    fn add_tree(&mut self, dir: &Path, mut events: Option<&mut Vec<FsEvent>>) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = self.fd.as_raw_fd();
+
+        // SAFETY: the path is a valid C string
+        let wd = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), MASK) };
+
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.dirs.insert(wd, dir.to_path_buf());
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if let Some(events) = events.as_deref_mut() {
+                events.push(FsEvent::Created(path.clone()));
+            }
+
+            if entry.file_type()?.is_dir() {
+                self.add_tree(&path, events.as_deref_mut())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes watches of the given directory and its children.
+    fn remove_tree(&mut self, dir: &Path) {
+        let fd = self.fd.as_raw_fd();
+
+        self.dirs.retain(|wd, path| {
+            if !path.starts_with(dir) {
+                return true;
+            }
+
+            // SAFETY: no pointer involved
+            unsafe { libc::inotify_rm_watch(fd, *wd) };
+            false
+        });
+    }
+
+    /// Waits for the inotify file descriptor to be readable.
+    fn wait(&self, timeout: Duration) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+        // SAFETY: the pointer is valid for the duration of the call
+        if unsafe { libc::poll(&mut pollfd, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads and translates all pending inotify events.
+    fn read_events(&mut self) -> io::Result<Vec<FsEvent>> {
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut events = Vec::new();
+        // moves are first reported as removals, then turned into
+        // renames when the matching destination is found
+        let mut moves = Vec::new();
+
+        loop {
+            let fd = self.fd.as_raw_fd();
+
+            // SAFETY: the pointer is valid for the buffer length
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+
+            if n < 0 {
+                let err = io::Error::last_os_error();
+
+                match err.kind() {
+                    io::ErrorKind::WouldBlock => break,
+                    io::ErrorKind::Interrupted => continue,
+                    _ => return Err(err),
+                }
+            }
+
+            let n = n as usize;
+            let mut offset = 0;
+
+            while offset + mem::size_of::<libc::inotify_event>() <= n {
+                // SAFETY: the kernel writes whole events, which may
+                // not be aligned within the buffer
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+
+                let start = offset + mem::size_of::<libc::inotify_event>();
+                let end = start + event.len as usize;
+                let name = buf[start..end].split(|b| *b == 0).next();
+                let name = OsStr::from_bytes(name.unwrap_or_default());
+                offset = end;
+
+                self.process(&event, name, &mut events, &mut moves);
+            }
+
+            if n == 0 {
+                break;
+            }
+        }
+
+        // directories moved outside the watched tree are still
+        // watched by inotify
+        for (_, index) in moves {
+            if let FsEvent::Removed(path) = &events[index] {
+                let path = path.clone();
+                self.remove_tree(&path);
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn process(
+        &mut self,
+        event: &libc::inotify_event,
+        name: &OsStr,
+        events: &mut Vec<FsEvent>,
+        moves: &mut Vec<(u32, usize)>,
+    ) {
+        let mask = event.mask;
+
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            warn!("inotify queue overflowed, some events have been lost");
+
+            if !events.contains(&FsEvent::Overflowed) {
+                events.push(FsEvent::Overflowed);
+            }
+
+            return;
+        }
+
+        if mask & libc::IN_IGNORED != 0 {
+            self.dirs.remove(&event.wd);
+            return;
+        }
+
+        let Some(dir) = self.dirs.get(&event.wd) else {
+            return;
+        };
+
+        let path = dir.join(name);
+        let is_dir = mask & libc::IN_ISDIR != 0;
+
+        if mask & libc::IN_CREATE != 0 {
+            events.push(FsEvent::Created(path.clone()));
+
+            if is_dir {
+                self.add_new_tree(&path, events);
+            }
+        } else if mask & libc::IN_MODIFY != 0 {
+            let event = FsEvent::Modified(path);
+
+            if events.last() != Some(&event) {
+                events.push(event);
+            }
+        } else if mask & libc::IN_DELETE != 0 {
+            events.push(FsEvent::Removed(path));
+        } else if mask & libc::IN_MOVED_FROM != 0 {
+            moves.push((event.cookie, events.len()));
+            events.push(FsEvent::Removed(path));
+        } else if mask & libc::IN_MOVED_TO != 0 {
+            let index = moves.iter().position(|(cookie, _)| *cookie == event.cookie);
+
+            let Some(index) = index.map(|i| moves.remove(i).1) else {
+                events.push(FsEvent::Created(path.clone()));
+
+                if is_dir {
+                    self.add_new_tree(&path, events);
+                }
+
+                return;
+            };
+
+            let FsEvent::Removed(from) = events[index].clone() else {
+                return;
+            };
+
+            if is_dir {
+                for dir in self.dirs.values_mut() {
+                    if let Ok(relative) = dir.strip_prefix(&from) {
+                        *dir = path.join(relative);
+                    }
+                }
+            }
+
+            events[index] = FsEvent::Renamed(from, path);
+        }
+    }
+
+    /// Watches a directory created after the watcher.
+    ///
+    /// Entries created inside the directory before its watch was
+    /// added are reported as created, so some of them may be
+    /// reported twice. The directory may already be gone, so errors
+    /// are only logged.
+    fn add_new_tree(&mut self, dir: &Path, events: &mut Vec<FsEvent>) {
+        if let Err(err) = self.add_tree(dir, Some(events)) {
+            debug!("cannot watch new directory {}: {err}", dir.display());
+        }
+    }
+}
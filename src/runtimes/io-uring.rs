@@ -0,0 +1,496 @@
+//! The io_uring-based, Linux-only filesystem runtime.
+//!
+//! Batch requests ([`FsIo::CreateFiles`], [`FsIo::ReadFiles`] and
+//! [`FsIo::RemoveFiles`]) are processed through an io_uring
+//! instance: every step (open, stat, read, write, close, unlink) is
+//! pushed for a window of paths at once then submitted with a
+//! single system call, instead of one system call chain per path.
+//! Windows are bounded by the queue capacity and by the limit of
+//! open files of the process, so that batches of any size can be
+//! processed. Other requests are processed by the
+//! [standard runtime].
+//!
+//! [standard runtime]: crate::runtimes::std
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::CString,
+    fmt, io,
+    mem::MaybeUninit,
+    ops::Range,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use ::io_uring::{opcode, squeue, types};
+use log::{debug, trace};
+
+use crate::io::FsIo;
+
+/// The default number of entries of the io_uring queues.
+pub const DEFAULT_ENTRIES: u32 = 256;
+
+/// The maximum number of bytes Linux transfers with a single read or
+/// write (`MAX_RW_COUNT`).
+const MAX_RW_LEN: usize = 0x7fff_f000;
+
+/// The `io_uring_enter` flag waiting for completions
+/// (`IORING_ENTER_GETEVENTS`).
+const ENTER_GETEVENTS: u32 = 1;
+
+/// The io_uring-based filesystem runtime.
+///
+/// Holds the io_uring instance used to process batch requests.
+pub struct IoUring {
+    ring: ::io_uring::IoUring,
+    entries: u32,
+    window: usize,
+    broken: bool,
+}
+
+impl IoUring {
+    /// Creates a new runtime with [`DEFAULT_ENTRIES`] queue entries.
+    pub fn new() -> io::Result<Self> {
+        Self::with_entries(DEFAULT_ENTRIES)
+    }
+
+    /// Creates a new runtime with the given number of queue entries.
+    ///
+    /// Batches bigger than the queue are split into several
+    /// submissions.
+    pub fn with_entries(entries: u32) -> io::Result<Self> {
+        let ring = ::io_uring::IoUring::new(entries)?;
+
+        // files are opened and stated within the same submission,
+        // and at most a quarter of the open files limit is used
+        let capacity = ring.params().sq_entries() as usize / 2;
+        let window = capacity.min(open_files_limit() / 4).max(1);
+
+        Ok(Self {
+            ring,
+            entries,
+            window,
+            broken: false,
+        })
+    }
+
+    /// The io_uring-based filesystem runtime handler.
+    pub fn handle(&mut self, input: FsIo) -> io::Result<FsIo> {
+        match input {
+            FsIo::CreateFiles(input) => self.create_files(input),
+            FsIo::ReadFiles(input) => self.read_files(input),
+            FsIo::RemoveFiles(input) => self.remove_files(input),
+            input => super::std::handle(input),
+        }
+    }
+
+    pub fn create_files(
+        &mut self,
+        input: Result<(), BTreeMap<PathBuf, Vec<u8>>>,
+    ) -> io::Result<FsIo> {
+        let Err(contents) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file contents"));
+        };
+
+        let (paths, contents): (Vec<_>, Vec<_>) = contents.into_iter().unzip();
+
+        for (paths, contents) in paths.chunks(self.window).zip(contents.chunks(self.window)) {
+            self.create_window(paths, contents)?;
+        }
+
+        Ok(FsIo::CreateFiles(Ok(())))
+    }
+
+    /// Opens, writes then closes the given window of files.
+    fn create_window(&mut self, paths: &[PathBuf], contents: &[Vec<u8>]) -> io::Result<()> {
+        let cpaths = to_cstrings(paths)?;
+
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC;
+        let entries = cpaths.iter().map(|path| {
+            opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr())
+                .flags(flags)
+                .mode(0o666)
+                .build()
+        });
+
+        let fds = self.submit(entries.collect())?;
+        let fds = Fds::new(paths, fds)?;
+
+        // writes can be partial, so they are resubmitted until all
+        // contents are written
+        let mut written = vec![0; contents.len()];
+
+        loop {
+            let pending: Vec<_> = (0..contents.len())
+                .filter(|&i| written[i] < contents[i].len())
+                .collect();
+
+            if pending.is_empty() {
+                break;
+            }
+
+            let entries = pending.iter().map(|&i| {
+                let buf = &contents[i][written[i]..];
+                let len = buf.len().min(MAX_RW_LEN) as u32;
+                opcode::Write::new(types::Fd(fds.0[i]), buf.as_ptr(), len)
+                    .offset(written[i] as u64)
+                    .build()
+            });
+
+            let results = self.submit(entries.collect())?;
+
+            for (&i, n) in pending.iter().zip(results) {
+                match n {
+                    n if n < 0 => return Err(error(&paths[i], n)),
+                    0 => return Err(io::ErrorKind::WriteZero.into()),
+                    n => written[i] += n as usize,
+                }
+            }
+        }
+
+        self.close(fds)
+    }
+
+    pub fn read_files(
+        &mut self,
+        input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>,
+    ) -> io::Result<FsIo> {
+        let Err(paths) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file paths"));
+        };
+
+        let paths: Vec<_> = paths.into_iter().collect();
+        let mut contents = BTreeMap::new();
+
+        for paths in paths.chunks(self.window) {
+            let window = self.read_window(paths)?;
+            contents.extend(paths.iter().cloned().zip(window));
+        }
+
+        Ok(FsIo::ReadFiles(Ok(contents)))
+    }
+
+    /// Opens, stats, reads then closes the given window of files.
+    ///
+    /// Returns the contents of the files, in the same order.
+    fn read_window(&mut self, paths: &[PathBuf]) -> io::Result<Vec<Vec<u8>>> {
+        let cpaths = to_cstrings(paths)?;
+
+        // files are opened and stated within the same submission
+        let mut stats = vec![MaybeUninit::<libc::statx>::zeroed(); paths.len()];
+        let flags = libc::O_RDONLY | libc::O_CLOEXEC;
+        let mut entries = Vec::with_capacity(paths.len() * 2);
+
+        for (path, stat) in cpaths.iter().zip(&mut stats) {
+            let entry = opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr())
+                .flags(flags)
+                .build();
+            entries.push(entry);
+
+            let entry = opcode::Statx::new(
+                types::Fd(libc::AT_FDCWD),
+                path.as_ptr(),
+                stat.as_mut_ptr().cast(),
+            )
+            .mask(libc::STATX_SIZE)
+            .build();
+            entries.push(entry);
+        }
+
+        let results = self.submit(entries)?;
+        let (fds, stat_results): (Vec<_>, Vec<_>) = results
+            .chunks_exact(2)
+            .map(|results| (results[0], results[1]))
+            .unzip();
+        let fds = Fds::new(paths, fds)?;
+
+        let mut contents = Vec::with_capacity(paths.len());
+
+        for (i, res) in stat_results.into_iter().enumerate() {
+            if res < 0 {
+                return Err(error(&paths[i], res));
+            }
+
+            // SAFETY: the kernel filled the buffer on success
+            let size = unsafe { stats[i].assume_init_ref() }.stx_size;
+
+            let Ok(size) = usize::try_from(size) else {
+                let kind = io::ErrorKind::FileTooLarge;
+                let msg = format!("{}: file too large for io_uring", paths[i].display());
+                return Err(io::Error::new(kind, msg));
+            };
+
+            contents.push(vec![0u8; size]);
+        }
+
+        // file contents are read based on their size at stat time;
+        // reads can be partial, so they are resubmitted until all
+        // contents are read or the end of the file is reached
+        let mut read = vec![0; contents.len()];
+        let mut eof = vec![false; contents.len()];
+
+        loop {
+            let pending: Vec<_> = (0..contents.len())
+                .filter(|&i| !eof[i] && read[i] < contents[i].len())
+                .collect();
+
+            if pending.is_empty() {
+                break;
+            }
+
+            let entries = pending.iter().map(|&i| {
+                let buf = &mut contents[i][read[i]..];
+                let len = buf.len().min(MAX_RW_LEN) as u32;
+                opcode::Read::new(types::Fd(fds.0[i]), buf.as_mut_ptr(), len)
+                    .offset(read[i] as u64)
+                    .build()
+            });
+
+            let results = self.submit(entries.collect())?;
+
+            for (&i, n) in pending.iter().zip(results) {
+                match n {
+                    n if n < 0 => return Err(error(&paths[i], n)),
+                    0 => eof[i] = true,
+                    n => read[i] += n as usize,
+                }
+            }
+        }
+
+        // files may have shrunk since stat time
+        for (buf, n) in contents.iter_mut().zip(read) {
+            buf.truncate(n);
+        }
+
+        self.close(fds)?;
+
+        Ok(contents)
+    }
+
+    pub fn remove_files(&mut self, input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
+        let Err(paths) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file paths"));
+        };
+
+        let paths: Vec<_> = paths.into_iter().collect();
+        let cpaths = to_cstrings(&paths)?;
+
+        let entries = cpaths
+            .iter()
+            .map(|path| opcode::UnlinkAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr()).build());
+
+        for (i, res) in self.submit(entries.collect())?.into_iter().enumerate() {
+            if res < 0 {
+                return Err(error(&paths[i], res));
+            }
+        }
+
+        Ok(FsIo::RemoveFiles(Ok(())))
+    }
+
+    /// Submits the given entries and waits for their completion.
+    ///
+    /// Returns the completion results, in the same order as the
+    /// given entries. Entries are submitted by chunks matching the
+    /// submission queue capacity.
+    ///
+    /// Pointers held by entries must remain valid until this
+    /// function returns. Interrupted submissions are retried until
+    /// all completions are received, so that no entry is still in
+    /// flight when this function returns.
+    fn submit(&mut self, entries: Vec<squeue::Entry>) -> io::Result<Vec<i32>> {
+        if self.broken {
+            let msg = "cannot submit io_uring entries: instance broken by a previous error";
+            return Err(io::Error::other(msg));
+        }
+
+        let capacity = self.ring.submission().capacity();
+        let mut results = vec![0; entries.len()];
+
+        for (n, chunk) in entries.chunks(capacity).enumerate() {
+            let offset = n * capacity;
+            let range = offset..offset + chunk.len();
+
+            // Errors other than the retried ones are returned by the
+            // kernel before consuming entries, which would then be
+            // submitted by the next call with dangling pointers: the
+            // instance stays broken until all completions are
+            // received, or until it is replaced.
+            self.broken = true;
+
+            for (i, entry) in chunk.iter().enumerate() {
+                let entry = entry.clone().user_data((offset + i) as u64);
+                // SAFETY: the queue has enough room for the chunk,
+                // and the caller keeps the pointed buffers alive
+                unsafe { self.ring.submission().push(&entry) }
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+            }
+
+            trace!("submit {} io_uring entries", chunk.len());
+            let mut pending = chunk.len();
+
+            while pending > 0 {
+                match self.ring.submit_and_wait(pending) {
+                    Ok(_) => (),
+                    Err(err) if is_retryable(&err) => {
+                        debug!("retry io_uring submission: {err}");
+                    }
+                    Err(err) => {
+                        // entries not consumed by the kernel are
+                        // discarded with the instance, once entries
+                        // in flight are completed
+                        let in_flight = pending - self.ring.submission().len();
+                        self.recover(in_flight, &range)?;
+                        return Err(err);
+                    }
+                }
+
+                for cqe in self.ring.completion() {
+                    let i = cqe.user_data() as usize;
+
+                    if !range.contains(&i) {
+                        debug!("ignore unexpected io_uring completion {i}");
+                        continue;
+                    }
+
+                    results[i] = cqe.result();
+                    pending -= 1;
+                }
+            }
+
+            self.broken = false;
+        }
+
+        Ok(results)
+    }
+
+    /// Waits for the given number of entries in flight to complete,
+    /// then replaces the io_uring instance, so that entries left in
+    /// the submission queue are never submitted.
+    ///
+    /// The instance stays broken if entries in flight cannot be
+    /// waited for.
+    fn recover(&mut self, mut in_flight: usize, range: &Range<usize>) -> io::Result<()> {
+        while in_flight > 0 {
+            let want = in_flight as u32;
+
+            // SAFETY: no entry is submitted, and no argument is given
+            let res = unsafe {
+                self.ring
+                    .submitter()
+                    .enter::<libc::sigset_t>(0, want, ENTER_GETEVENTS, None)
+            };
+
+            match res {
+                Ok(_) => (),
+                Err(err) if is_retryable(&err) => {
+                    debug!("retry waiting for io_uring completions: {err}");
+                }
+                Err(err) => return Err(err),
+            }
+
+            for cqe in self.ring.completion() {
+                if range.contains(&(cqe.user_data() as usize)) {
+                    in_flight -= 1;
+                }
+            }
+        }
+
+        debug!("replace io_uring instance after submission error");
+        self.ring = ::io_uring::IoUring::new(self.entries)?;
+        self.broken = false;
+
+        Ok(())
+    }
+
+    /// Closes the given file descriptors within a single submission.
+    fn close(&mut self, mut fds: Fds) -> io::Result<()> {
+        let fds = std::mem::take(&mut fds.0);
+        let entries = fds
+            .iter()
+            .map(|&fd| opcode::Close::new(types::Fd(fd)).build());
+
+        for res in self.submit(entries.collect())? {
+            if res < 0 {
+                return Err(io::Error::from_raw_os_error(-res));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for IoUring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoUring").finish_non_exhaustive()
+    }
+}
+
+/// File descriptors opened through io_uring.
+///
+/// File descriptors are closed on drop, which only happens when an
+/// error occurs before they can be closed through io_uring.
+struct Fds(Vec<i32>);
+
+impl Fds {
+    /// Collects file descriptors from the given open results.
+    ///
+    /// Returns the first open error, after closing already opened
+    /// file descriptors.
+    fn new(paths: &[PathBuf], results: Vec<i32>) -> io::Result<Self> {
+        let fds = Self(results.iter().copied().filter(|&fd| fd >= 0).collect());
+
+        if let Some(i) = results.iter().position(|&fd| fd < 0) {
+            return Err(error(&paths[i], results[i]));
+        }
+
+        Ok(fds)
+    }
+}
+
+impl Drop for Fds {
+    fn drop(&mut self) {
+        for fd in &self.0 {
+            debug!("close file descriptor {fd} after io_uring error");
+            // SAFETY: file descriptors are owned by this struct
+            unsafe { libc::close(*fd) };
+        }
+    }
+}
+
+/// Returns the soft limit of open files of the process.
+fn open_files_limit() -> usize {
+    let mut limit = MaybeUninit::<libc::rlimit>::zeroed();
+
+    // SAFETY: the kernel fills the given buffer on success
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr()) } != 0 {
+        return usize::MAX;
+    }
+
+    // SAFETY: the buffer is filled on success
+    let limit = unsafe { limit.assume_init() }.rlim_cur;
+    usize::try_from(limit).unwrap_or(usize::MAX)
+}
+
+fn to_cstrings(paths: &[PathBuf]) -> io::Result<Vec<CString>> {
+    paths
+        .iter()
+        .map(|path| CString::new(path.as_os_str().as_bytes()))
+        .collect::<Result<_, _>>()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Returns `true` if the given submission error is transient:
+/// interrupted by a signal, or temporarily out of resources.
+fn is_retryable(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::Interrupted
+        || matches!(err.raw_os_error(), Some(libc::EAGAIN | libc::EBUSY))
+}
+
+fn error(path: &Path, res: i32) -> io::Error {
+    let err = io::Error::from_raw_os_error(-res);
+    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
@@ -10,7 +10,43 @@
 //! [I/O]: crate::io::FsIo
 //! [coroutines]: crate::coroutines
 
+use ::std::path::PathBuf;
+
+#[path = "dry-run.rs"]
+pub mod dry_run;
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "std", feature = "tokio", feature = "smol")
+))]
+mod inotify;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[path = "io-uring.rs"]
+pub mod io_uring;
+#[path = "read-only.rs"]
+pub mod read_only;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "smol")]
+pub mod smol;
 #[cfg(feature = "std")]
 pub mod std;
+#[cfg(any(feature = "std", feature = "tokio", feature = "smol"))]
+mod sys;
+#[cfg(feature = "std")]
+#[path = "thread-pool.rs"]
+pub mod thread_pool;
 #[cfg(feature = "tokio")]
 pub mod tokio;
+pub mod transcript;
+
+/// Sorts the given paths by depth, shallowest first.
+///
+/// Paths sharing the same depth keep their relative order. Runtimes
+/// should create directories in this order, and remove them in the
+/// reverse order, so that batch requests can safely contain both a
+/// directory and its descendants.
+pub fn sort_by_depth(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = paths.into_iter().collect();
+    paths.sort_by_key(|path| path.components().count());
+    paths
+}
@@ -0,0 +1,87 @@
+//! The read-only filesystem runtime wrapper.
+//!
+//! This module does not process I/O by itself: it wraps another
+//! runtime handler and rejects every request that would mutate the
+//! filesystem, so that coroutines can safely inspect real
+//! directories.
+//!
+//! ```rust,ignore
+//! use io_fs::runtimes::{read_only::ReadOnly, std, tokio};
+//!
+//! // blocking runtimes
+//! let mut runtime = ReadOnly::new(std::handle);
+//! let output = runtime.handle(io)?;
+//!
+//! // async runtimes
+//! let mut runtime = ReadOnly::new(tokio::handle);
+//! let output = runtime.handle_async(io).await?;
+//! ```
+
+use std::{future::Future, io};
+
+use log::debug;
+
+use crate::io::FsIo;
+
+/// Checks that the given I/O request does not mutate the
+/// filesystem.
+///
+/// Returns an error of kind [`io::ErrorKind::PermissionDenied`] for
+/// every mutating request (see [`FsIo::is_mutation`]).
+pub fn check(input: &FsIo) -> io::Result<()> {
+    if !input.is_mutation() {
+        return Ok(());
+    }
+
+    debug!("deny {input:?} from read-only runtime");
+
+    let kind = io::ErrorKind::PermissionDenied;
+    let msg = format!("cannot process {input:?}: read-only runtime");
+    Err(io::Error::new(kind, msg))
+}
+
+/// The read-only filesystem runtime handler.
+///
+/// Read requests are forwarded to the given blocking runtime
+/// `handler`, mutating requests are rejected (see [`check`]).
+pub fn handle(input: FsIo, handler: impl FnOnce(FsIo) -> io::Result<FsIo>) -> io::Result<FsIo> {
+    check(&input)?;
+    handler(input)
+}
+
+/// The read-only filesystem runtime wrapper.
+///
+/// Wraps a blocking or async runtime handler, so that every request
+/// is [checked](check) before reaching it.
+#[derive(Clone, Debug)]
+pub struct ReadOnly<H> {
+    handler: H,
+}
+
+impl<H> ReadOnly<H> {
+    /// Wraps the given runtime handler.
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+
+    /// Forwards the given read request to the wrapped blocking
+    /// handler, rejects mutating requests (see [`check`]).
+    pub fn handle(&mut self, input: FsIo) -> io::Result<FsIo>
+    where
+        H: FnMut(FsIo) -> io::Result<FsIo>,
+    {
+        check(&input)?;
+        (self.handler)(input)
+    }
+
+    /// Forwards the given read request to the wrapped async handler,
+    /// rejects mutating requests (see [`check`]).
+    pub async fn handle_async<F>(&mut self, input: FsIo) -> io::Result<FsIo>
+    where
+        H: FnMut(FsIo) -> F,
+        F: Future<Output = io::Result<FsIo>>,
+    {
+        check(&input)?;
+        (self.handler)(input).await
+    }
+}
@@ -0,0 +1,145 @@
+//! The out-of-process filesystem runtime.
+//!
+//! This module splits a runtime in two: a [`Client`], which forwards
+//! I/O requests emitted by coroutines to another process, and a
+//! [`serve`] function, which processes them with a real runtime
+//! handler. Coroutines can then be driven by a process that has no
+//! filesystem access at all.
+//!
+//! The wire protocol is line-based: every message is a JSON
+//! document followed by a line feed. The client sends one serialized
+//! [`FsIo`] request, the server replies with one serialized
+//! `Result<FsIo, FsIoError>` response. Any byte stream can be used
+//! as transport: Unix sockets, pipes, stdin/stdout of a child
+//! process etc.
+//!
+//! ```rust,ignore
+//! use std::{os::unix::net::UnixStream, thread};
+//!
+//! use io_fs::runtimes::{remote::{serve, Client}, std::handle};
+//!
+//! let (client, server) = UnixStream::pair()?;
+//!
+//! thread::spawn(move || serve(server.try_clone()?, server, handle));
+//!
+//! let mut client = Client::new(client.try_clone()?, client);
+//! let output = client.handle(io)?;
+//! ```
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use log::{debug, trace};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::FsIoError, io::FsIo};
+
+/// The out-of-process filesystem runtime client.
+///
+/// Holds the transport used to exchange messages with the server.
+#[derive(Debug)]
+pub struct Client<R, W> {
+    reader: BufReader<R>,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Client<R, W> {
+    /// Creates a new client from the given transport halves.
+    pub fn new(reader: R, writer: W) -> Self {
+        let reader = BufReader::new(reader);
+        Self { reader, writer }
+    }
+
+    /// The out-of-process filesystem runtime handler.
+    ///
+    /// Sends the given request to the server, then waits for its
+    /// response. Errors returned by the server are converted back
+    /// into [`io::Error`].
+    pub fn handle(&mut self, input: FsIo) -> io::Result<FsIo> {
+        debug!("forward {input:?} to remote runtime");
+        write_message(&mut self.writer, &input)?;
+
+        let Some(output) = read_message::<Result<FsIo, FsIoError>>(&mut self.reader)? else {
+            let kind = io::ErrorKind::UnexpectedEof;
+            return Err(io::Error::new(kind, "remote runtime closed the connection"));
+        };
+
+        Ok(output?)
+    }
+}
+
+/// Processes requests sent by a [`Client`] until the transport is
+/// closed.
+///
+/// Requests are processed using the given blocking runtime
+/// `handler`, typically [`crate::runtimes::std::handle`]. Runtime
+/// errors, as well as outputs that cannot be serialized (like paths
+/// that are not valid UTF-8), are sent back to the client as errors:
+/// only transport errors stop the server.
+pub fn serve(
+    reader: impl Read,
+    mut writer: impl Write,
+    mut handler: impl FnMut(FsIo) -> io::Result<FsIo>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let output = match read_message::<FsIo>(&mut reader) {
+            Ok(Some(input)) => {
+                debug!("process {input:?} from remote client");
+                handler(input).map_err(FsIoError::from)
+            }
+            Ok(None) => break,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                debug!("reject invalid message from remote client: {err}");
+                Err(FsIoError::from(err))
+            }
+            Err(err) => return Err(err),
+        };
+
+        // the output is serialized before writing anything, so that
+        // the client still receives a response on failure
+        let line = match to_line(&output) {
+            Ok(line) => line,
+            Err(err) => {
+                debug!("reject unserializable output for remote client: {err}");
+                to_line(&Err::<FsIo, _>(FsIoError::from(err)))?
+            }
+        };
+
+        write_line(&mut writer, &line)?;
+    }
+
+    debug!("remote client closed the connection");
+
+    Ok(())
+}
+
+fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let line = to_line(message)?;
+    write_line(writer, &line)
+}
+
+fn to_line(message: &impl Serialize) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn write_line(writer: &mut impl Write, line: &[u8]) -> io::Result<()> {
+    trace!("write message of {} bytes", line.len());
+
+    writer.write_all(line)?;
+    writer.flush()
+}
+
+fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = Vec::new();
+
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    trace!("read message of {} bytes", line.len());
+
+    Ok(Some(serde_json::from_slice(&line)?))
+}
@@ -0,0 +1,407 @@
+//! The smol-based, async filesystem runtime.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use async_fs as fs;
+use blocking::unblock;
+use futures_lite::StreamExt;
+use log::debug;
+
+use crate::{
+    io::{FsChunk, FsEvent, FsIo, FsMetadata, LockMode, RenameMode},
+    runtimes::{sort_by_depth, sys},
+};
+
+/// The smol-based, async filesystem runtime handler.
+///
+/// This handler makes use of standard module [`std::io`] and smol
+/// crate [`async_fs`] to process [`FsIo`].
+pub async fn handle(input: FsIo) -> io::Result<FsIo> {
+    match input {
+        FsIo::AppendFile(input) => append_file(input).await,
+        FsIo::CreateDir(input) => create_dir(input).await,
+        FsIo::CreateDirs(input) => create_dirs(input).await,
+        FsIo::CreateFile(input) => create_file(input).await,
+        FsIo::CreateFiles(input) => create_files(input).await,
+        FsIo::CreateNewFile(input) => create_new_file(input).await,
+        FsIo::Error(err) => {
+            let kind = io::ErrorKind::InvalidInput;
+            let msg = format!("cannot process error output: {err}");
+            Err(io::Error::new(kind, msg))
+        }
+        FsIo::Lock(input) => lock(input).await,
+        FsIo::PollEvents(input) => poll_events(input).await,
+        FsIo::ReadChunks(input) => read_chunks(input).await,
+        FsIo::ReadDir(input) => read_dir(input).await,
+        FsIo::ReadFile(input) => read_file(input).await,
+        FsIo::ReadFiles(input) => read_files(input).await,
+        FsIo::ReadMetadata(input) => read_metadata(input).await,
+        FsIo::ReadMetadatas(input) => read_metadatas(input).await,
+        FsIo::ReadTargetMetadata(input) => read_target_metadata(input).await,
+        FsIo::RemoveDir(input) => remove_dir(input).await,
+        FsIo::RemoveDirs(input) => remove_dirs(input).await,
+        FsIo::RemoveFile(input) => remove_file(input).await,
+        FsIo::RemoveFiles(input) => remove_files(input).await,
+        FsIo::Rename(input) => rename(input).await,
+        FsIo::SetModified(input) => set_modified(input).await,
+        FsIo::SyncDir(input) => sync_dir(input).await,
+        FsIo::SyncFile(input) => sync_file(input).await,
+        FsIo::Unlock(input) => unlock(input).await,
+        FsIo::Unwatch(input) => unwatch(input).await,
+        FsIo::Watch(input) => watch(input).await,
+    }
+}
+
+pub async fn append_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    unblock(move || sys::append_file(&path, &contents)).await?;
+
+    Ok(FsIo::AppendFile(Ok(())))
+}
+
+pub async fn create_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    fs::create_dir(path).await?;
+
+    Ok(FsIo::CreateDir(Ok(())))
+}
+
+pub async fn create_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory paths"));
+    };
+
+    // parents need to be created before their children
+    for path in sort_by_depth(paths) {
+        fs::create_dir(path).await?;
+    }
+
+    Ok(FsIo::CreateDirs(Ok(())))
+}
+
+pub async fn create_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file contents"));
+    };
+
+    fs::write(path, contents).await?;
+
+    Ok(FsIo::CreateFile(Ok(())))
+}
+
+pub async fn create_files(input: Result<(), BTreeMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
+    let Err(contents) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file contents"));
+    };
+
+    for (path, contents) in contents {
+        fs::write(path, contents).await?;
+    }
+
+    Ok(FsIo::CreateFiles(Ok(())))
+}
+
+pub async fn create_new_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    unblock(move || sys::create_new_file(&path, &contents)).await?;
+
+    Ok(FsIo::CreateNewFile(Ok(())))
+}
+
+pub async fn lock(input: Result<u64, (PathBuf, LockMode)>) -> io::Result<FsIo> {
+    let Err((path, mode)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let lock = move || sys::lock(&path, mode).map(sys::LockGuard::new);
+    let guard = unblock(lock).await?;
+
+    Ok(FsIo::Lock(Ok(guard.into_id())))
+}
+
+pub async fn poll_events(input: Result<Vec<FsEvent>, (u64, Duration)>) -> io::Result<FsIo> {
+    let Err((id, timeout)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    let events = unblock(move || sys::poll_events(id, timeout)).await?;
+
+    Ok(FsIo::PollEvents(Ok(events)))
+}
+
+pub async fn read_chunks(
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeMap<PathBuf, FsChunk>>,
+) -> io::Result<FsIo> {
+    let Err(chunks) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file chunks"));
+    };
+
+    let mut contents = BTreeMap::new();
+
+    for (path, FsChunk { offset, len }) in chunks {
+        let chunk = {
+            let path = path.clone();
+            unblock(move || sys::read_chunk(&path, offset, len)).await?
+        };
+        contents.insert(path, chunk);
+    }
+
+    Ok(FsIo::ReadChunks(Ok(contents)))
+}
+
+pub async fn read_dir(input: Result<BTreeSet<PathBuf>, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    let mut paths = BTreeSet::new();
+    let mut dir = fs::read_dir(path).await?;
+
+    while let Some(entry) = dir.next().await {
+        match entry {
+            Ok(entry) => {
+                paths.insert(entry.path());
+            }
+            Err(err) => {
+                debug!("ignore invalid directory entry: {err}");
+                continue;
+            }
+        };
+    }
+
+    Ok(FsIo::ReadDir(Ok(paths)))
+}
+
+pub async fn read_file(input: Result<Vec<u8>, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path"));
+    };
+
+    let contents = fs::read(path).await?;
+
+    Ok(FsIo::ReadFile(Ok(contents)))
+}
+
+pub async fn read_files(
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>,
+) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file paths"));
+    };
+
+    let mut contents = BTreeMap::new();
+
+    for path in paths {
+        let content = fs::read(&path).await?;
+        contents.insert(path, content);
+    }
+
+    Ok(FsIo::ReadFiles(Ok(contents)))
+}
+
+pub async fn read_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::symlink_metadata(path).await?;
+
+    Ok(FsIo::ReadMetadata(Ok(metadata.into())))
+}
+
+pub async fn read_metadatas(
+    input: Result<BTreeMap<PathBuf, FsMetadata>, BTreeSet<PathBuf>>,
+) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry paths"));
+    };
+
+    let mut metadatas = BTreeMap::new();
+
+    for path in paths {
+        let metadata = fs::symlink_metadata(&path).await?;
+        metadatas.insert(path, metadata.into());
+    }
+
+    Ok(FsIo::ReadMetadatas(Ok(metadatas)))
+}
+
+pub async fn read_target_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::metadata(path).await?;
+
+    Ok(FsIo::ReadTargetMetadata(Ok(metadata.into())))
+}
+
+pub async fn remove_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    fs::remove_dir_all(path).await?;
+
+    Ok(FsIo::RemoveDir(Ok(())))
+}
+
+pub async fn remove_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory paths"));
+    };
+
+    // children need to be removed before their parents
+    for path in sort_by_depth(paths).into_iter().rev() {
+        fs::remove_dir_all(path).await?;
+    }
+
+    Ok(FsIo::RemoveDirs(Ok(())))
+}
+
+pub async fn remove_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path"));
+    };
+
+    fs::remove_file(path).await?;
+
+    Ok(FsIo::RemoveFile(Ok(())))
+}
+
+pub async fn remove_files(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file paths"));
+    };
+
+    for path in paths {
+        fs::remove_file(path).await?;
+    }
+
+    Ok(FsIo::RemoveFiles(Ok(())))
+}
+
+pub async fn rename(input: Result<(), (Vec<(PathBuf, PathBuf)>, RenameMode)>) -> io::Result<FsIo> {
+    let Err((paths, mode)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file paths"));
+    };
+
+    for (from, to) in paths {
+        match mode {
+            RenameMode::Replace => fs::rename(from, to).await?,
+            mode => unblock(move || sys::rename(&from, &to, mode)).await?,
+        }
+    }
+
+    Ok(FsIo::Rename(Ok(())))
+}
+
+pub async fn set_modified(input: Result<(), BTreeMap<PathBuf, SystemTime>>) -> io::Result<FsIo> {
+    let Err(times) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(
+            kind,
+            "missing file paths and modification times",
+        ));
+    };
+
+    unblock(move || {
+        for (path, time) in times {
+            sys::set_modified(&path, time)?;
+        }
+
+        io::Result::Ok(())
+    })
+    .await?;
+
+    Ok(FsIo::SetModified(Ok(())))
+}
+
+pub async fn sync_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    unblock(move || sys::sync_dir(&path)).await?;
+
+    Ok(FsIo::SyncDir(Ok(())))
+}
+
+pub async fn sync_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path"));
+    };
+
+    unblock(move || sys::sync_file(&path)).await?;
+
+    Ok(FsIo::SyncFile(Ok(())))
+}
+
+pub async fn unlock(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing lock identifier"));
+    };
+
+    sys::unlock(id)?;
+
+    Ok(FsIo::Unlock(Ok(())))
+}
+
+pub async fn unwatch(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    sys::unwatch(id)?;
+
+    Ok(FsIo::Unwatch(Ok(())))
+}
+
+pub async fn watch(input: Result<u64, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    let id = unblock(move || sys::watch(&path)).await?;
+
+    Ok(FsIo::Watch(Ok(id)))
+}
@@ -1,14 +1,18 @@
 //! The standard, blocking filesystem runtime.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet},
     fs, io,
     path::PathBuf,
+    time::{Duration, SystemTime},
 };
 
 use log::debug;
 
-use crate::io::FsIo;
+use crate::{
+    io::{FsChunk, FsEvent, FsIo, FsMetadata, LockMode, RenameMode},
+    runtimes::{sort_by_depth, sys},
+};
 
 /// The standard, blocking filesystem runtime handler.
 ///
@@ -16,21 +20,51 @@ use crate::io::FsIo;
 /// [`std::io`] to process [`FsIo`].
 pub fn handle(input: FsIo) -> io::Result<FsIo> {
     match input {
+        FsIo::AppendFile(input) => append_file(input),
         FsIo::CreateDir(input) => create_dir(input),
         FsIo::CreateDirs(input) => create_dirs(input),
         FsIo::CreateFile(input) => create_file(input),
         FsIo::CreateFiles(input) => create_files(input),
+        FsIo::CreateNewFile(input) => create_new_file(input),
+        FsIo::Error(err) => {
+            let kind = io::ErrorKind::InvalidInput;
+            let msg = format!("cannot process error output: {err}");
+            Err(io::Error::new(kind, msg))
+        }
+        FsIo::Lock(input) => lock(input),
+        FsIo::PollEvents(input) => poll_events(input),
+        FsIo::ReadChunks(input) => read_chunks(input),
         FsIo::ReadDir(input) => read_dir(input),
         FsIo::ReadFile(input) => read_file(input),
         FsIo::ReadFiles(input) => read_files(input),
+        FsIo::ReadMetadata(input) => read_metadata(input),
+        FsIo::ReadMetadatas(input) => read_metadatas(input),
+        FsIo::ReadTargetMetadata(input) => read_target_metadata(input),
         FsIo::RemoveDir(input) => remove_dir(input),
         FsIo::RemoveDirs(input) => remove_dirs(input),
         FsIo::RemoveFile(input) => remove_file(input),
         FsIo::RemoveFiles(input) => remove_files(input),
         FsIo::Rename(input) => rename(input),
+        FsIo::SetModified(input) => set_modified(input),
+        FsIo::SyncDir(input) => sync_dir(input),
+        FsIo::SyncFile(input) => sync_file(input),
+        FsIo::Unlock(input) => unlock(input),
+        FsIo::Unwatch(input) => unwatch(input),
+        FsIo::Watch(input) => watch(input),
     }
 }
 
+pub fn append_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    sys::append_file(&path, &contents)?;
+
+    Ok(FsIo::AppendFile(Ok(())))
+}
+
 pub fn create_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
@@ -42,13 +76,14 @@ pub fn create_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::CreateDir(Ok(())))
 }
 
-pub fn create_dirs(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub fn create_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory paths"));
     };
 
-    for path in paths {
+    // parents need to be created before their children
+    for path in sort_by_depth(paths) {
         fs::create_dir(path)?;
     }
 
@@ -66,7 +101,7 @@ pub fn create_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
     Ok(FsIo::CreateFile(Ok(())))
 }
 
-pub fn create_files(input: Result<(), HashMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
+pub fn create_files(input: Result<(), BTreeMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
     let Err(contents) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file contents"));
@@ -79,13 +114,64 @@ pub fn create_files(input: Result<(), HashMap<PathBuf, Vec<u8>>>) -> io::Result<
     Ok(FsIo::CreateFiles(Ok(())))
 }
 
-pub fn read_dir(input: Result<HashSet<PathBuf>, PathBuf>) -> io::Result<FsIo> {
+pub fn create_new_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    sys::create_new_file(&path, &contents)?;
+
+    Ok(FsIo::CreateNewFile(Ok(())))
+}
+
+pub fn lock(input: Result<u64, (PathBuf, LockMode)>) -> io::Result<FsIo> {
+    let Err((path, mode)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let id = sys::lock(&path, mode)?;
+
+    Ok(FsIo::Lock(Ok(id)))
+}
+
+pub fn poll_events(input: Result<Vec<FsEvent>, (u64, Duration)>) -> io::Result<FsIo> {
+    let Err((id, timeout)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    let events = sys::poll_events(id, timeout)?;
+
+    Ok(FsIo::PollEvents(Ok(events)))
+}
+
+pub fn read_chunks(
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeMap<PathBuf, FsChunk>>,
+) -> io::Result<FsIo> {
+    let Err(chunks) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file chunks"));
+    };
+
+    let mut contents = BTreeMap::new();
+
+    for (path, FsChunk { offset, len }) in chunks {
+        let chunk = sys::read_chunk(&path, offset, len)?;
+        contents.insert(path, chunk);
+    }
+
+    Ok(FsIo::ReadChunks(Ok(contents)))
+}
+
+pub fn read_dir(input: Result<BTreeSet<PathBuf>, PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory path"));
     };
 
-    let mut paths = HashSet::new();
+    let mut paths = BTreeSet::new();
     let dir = fs::read_dir(path)?;
 
     for entry in dir {
@@ -114,13 +200,15 @@ pub fn read_file(input: Result<Vec<u8>, PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::ReadFile(Ok(contents)))
 }
 
-pub fn read_files(input: Result<HashMap<PathBuf, Vec<u8>>, HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub fn read_files(
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>,
+) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
     };
 
-    let mut contents = HashMap::new();
+    let mut contents = BTreeMap::new();
 
     for path in paths {
         let content = fs::read(&path)?;
@@ -130,6 +218,46 @@ pub fn read_files(input: Result<HashMap<PathBuf, Vec<u8>>, HashSet<PathBuf>>) ->
     Ok(FsIo::ReadFiles(Ok(contents)))
 }
 
+pub fn read_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::symlink_metadata(path)?;
+
+    Ok(FsIo::ReadMetadata(Ok(metadata.into())))
+}
+
+pub fn read_metadatas(
+    input: Result<BTreeMap<PathBuf, FsMetadata>, BTreeSet<PathBuf>>,
+) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry paths"));
+    };
+
+    let mut metadatas = BTreeMap::new();
+
+    for path in paths {
+        let metadata = fs::symlink_metadata(&path)?;
+        metadatas.insert(path, metadata.into());
+    }
+
+    Ok(FsIo::ReadMetadatas(Ok(metadatas)))
+}
+
+pub fn read_target_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::metadata(path)?;
+
+    Ok(FsIo::ReadTargetMetadata(Ok(metadata.into())))
+}
+
 pub fn remove_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
@@ -141,13 +269,14 @@ pub fn remove_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::RemoveDir(Ok(())))
 }
 
-pub fn remove_dirs(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub fn remove_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory paths"));
     };
 
-    for path in paths {
+    // children need to be removed before their parents
+    for path in sort_by_depth(paths).into_iter().rev() {
         fs::remove_dir_all(path)?;
     }
 
@@ -165,7 +294,7 @@ pub fn remove_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::RemoveFile(Ok(())))
 }
 
-pub fn remove_files(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub fn remove_files(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
@@ -178,15 +307,86 @@ pub fn remove_files(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
     Ok(FsIo::RemoveFiles(Ok(())))
 }
 
-pub fn rename(input: Result<(), Vec<(PathBuf, PathBuf)>>) -> io::Result<FsIo> {
-    let Err(paths) = input else {
+pub fn rename(input: Result<(), (Vec<(PathBuf, PathBuf)>, RenameMode)>) -> io::Result<FsIo> {
+    let Err((paths, mode)) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
     };
 
     for (from, to) in paths {
-        fs::rename(from, to)?;
+        sys::rename(&from, &to, mode)?;
     }
 
     Ok(FsIo::Rename(Ok(())))
 }
+
+pub fn set_modified(input: Result<(), BTreeMap<PathBuf, SystemTime>>) -> io::Result<FsIo> {
+    let Err(times) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(
+            kind,
+            "missing file paths and modification times",
+        ));
+    };
+
+    for (path, time) in times {
+        sys::set_modified(&path, time)?;
+    }
+
+    Ok(FsIo::SetModified(Ok(())))
+}
+
+pub fn sync_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    sys::sync_dir(&path)?;
+
+    Ok(FsIo::SyncDir(Ok(())))
+}
+
+pub fn sync_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path"));
+    };
+
+    sys::sync_file(&path)?;
+
+    Ok(FsIo::SyncFile(Ok(())))
+}
+
+pub fn unlock(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing lock identifier"));
+    };
+
+    sys::unlock(id)?;
+
+    Ok(FsIo::Unlock(Ok(())))
+}
+
+pub fn unwatch(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    sys::unwatch(id)?;
+
+    Ok(FsIo::Unwatch(Ok(())))
+}
+
+pub fn watch(input: Result<u64, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    let id = sys::watch(&path)?;
+
+    Ok(FsIo::Watch(Ok(id)))
+}
@@ -0,0 +1,376 @@
+//! Blocking, low-level filesystem helpers shared by runtimes.
+//!
+//! These helpers cover operations that neither [`std::fs`] nor async
+//! filesystem crates expose.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::SystemTime,
+};
+
+#[cfg(target_os = "linux")]
+pub use super::inotify::{poll_events, unwatch, watch};
+use crate::io::{LockMode, RenameMode};
+
+/// Files held open to keep their advisory lock, by lock identifier.
+static LOCKS: Mutex<BTreeMap<u64, File>> = Mutex::new(BTreeMap::new());
+
+/// The identifier of the next advisory lock.
+static NEXT_LOCK_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The maximum number of files kept open between chunk reads.
+const MAX_READERS: usize = 64;
+
+/// Files kept open between chunk reads, by path.
+static READERS: Mutex<BTreeMap<PathBuf, Reader>> = Mutex::new(BTreeMap::new());
+
+/// The number of chunk reads, used to evict the least recently used
+/// reader.
+static CHUNK_READS: AtomicU64 = AtomicU64::new(0);
+
+/// A file kept open between chunk reads.
+struct Reader {
+    file: File,
+    /// The offset of the next chunk.
+    next: u64,
+    /// The chunk read that last used the file.
+    used: u64,
+}
+
+/// Renames the given source path to the given destination path,
+/// according to the given mode.
+pub fn rename(from: &Path, to: &Path, mode: RenameMode) -> io::Result<()> {
+    match mode {
+        RenameMode::Replace => fs::rename(from, to),
+        RenameMode::NoReplace => rename_no_replace(from, to),
+        RenameMode::Exchange => rename_exchange(from, to),
+    }
+}
+
+/// Reads at most `len` bytes of the given file, starting at the given
+/// offset.
+///
+/// The returned chunk is shorter than `len` only when the end of the
+/// file has been reached.
+///
+/// Files are kept open until their end is reached, so that reading
+/// the next chunk neither reopens nor seeks them. A file is reopened
+/// when the requested offset is not the one of its next chunk. When
+/// too many files are kept open, the least recently used one is
+/// closed, so that abandoned reads do not keep files open forever.
+pub fn read_chunk(path: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    // a reader expecting another offset is dropped, hence closed
+    let mut file = match readers().remove(path) {
+        Some(reader) if reader.next == offset => reader.file,
+        _ => {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            file
+        }
+    };
+
+    let mut chunk = Vec::new();
+    (&mut file).take(len).read_to_end(&mut chunk)?;
+    let next = offset + chunk.len() as u64;
+
+    // a short chunk, or a chunk ending the file, means that the file
+    // is fully read, it is then closed
+    if chunk.len() as u64 != len || next >= file.metadata()?.len() {
+        return Ok(chunk);
+    }
+
+    let used = CHUNK_READS.fetch_add(1, Ordering::Relaxed);
+    let mut readers = readers();
+
+    if readers.len() >= MAX_READERS {
+        let lru = readers
+            .iter()
+            .min_by_key(|(_, reader)| reader.used)
+            .map(|(path, _)| path.clone());
+
+        if let Some(lru) = lru {
+            readers.remove(&lru);
+        }
+    }
+
+    let reader = Reader { file, next, used };
+    readers.insert(path.to_path_buf(), reader);
+
+    Ok(chunk)
+}
+
+/// Creates the given file with the given contents, failing if it
+/// already exists.
+pub fn create_new_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(contents)
+}
+
+/// Appends the given contents to the given file, creating it if it
+/// does not exist.
+///
+/// Contents are written with a single write, which fails if it is
+/// short instead of being continued by another write.
+pub fn append_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+
+    let n = loop {
+        match file.write(contents) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            res => break res?,
+        }
+    };
+
+    if n < contents.len() {
+        let kind = io::ErrorKind::WriteZero;
+        let len = contents.len();
+        let msg = format!(
+            "cannot append to {}: wrote {n} of {len} bytes",
+            path.display()
+        );
+        return Err(io::Error::new(kind, msg));
+    }
+
+    Ok(())
+}
+
+/// Sets the last modification time of the given file.
+pub fn set_modified(path: &Path, time: SystemTime) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_modified(time)
+}
+
+/// Flushes contents and metadata of the given file to disk.
+#[cfg(unix)]
+pub fn sync_file(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Flushes contents and metadata of the given file to disk.
+///
+/// Files need to be opened with write access for their buffers to be
+/// flushed on this platform.
+#[cfg(not(unix))]
+pub fn sync_file(path: &Path) -> io::Result<()> {
+    OpenOptions::new().write(true).open(path)?.sync_all()
+}
+
+/// Flushes entries of the given directory to disk.
+#[cfg(unix)]
+pub fn sync_dir(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Flushes entries of the given directory to disk.
+///
+/// Directories cannot be opened for syncing on this platform, where
+/// directory entries are expected to be durable on their own.
+#[cfg(not(unix))]
+pub fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Acquires an advisory lock on the given entry, according to the
+/// given mode.
+///
+/// The entry is opened anew for each lock and kept open until it is
+/// [unlocked](unlock), so that locks acquired on the same entry
+/// conflict with each other, even within the same process.
+///
+/// Returns the identifier of the lock.
+pub fn lock(path: &Path, mode: LockMode) -> io::Result<u64> {
+    let file = File::open(path)?;
+
+    // the registry is not locked while waiting for the lock
+    flock(&file, mode)?;
+
+    let id = NEXT_LOCK_ID.fetch_add(1, Ordering::Relaxed);
+    locks().insert(id, file);
+
+    Ok(id)
+}
+
+/// Releases the advisory lock of the given identifier.
+/// An advisory lock released on drop, unless its identifier is taken
+/// with [`LockGuard::into_id`].
+///
+/// Async runtimes acquire locks within blocking tasks, which keep
+/// running when the awaiting future is dropped: the guard releases
+/// locks whose identifier is never delivered.
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub struct LockGuard(u64);
+
+#[cfg(any(feature = "tokio", feature = "smol"))]
+impl LockGuard {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// Returns the lock identifier, without releasing the lock.
+    pub fn into_id(self) -> u64 {
+        let id = self.0;
+        std::mem::forget(self);
+        id
+    }
+}
+
+#[cfg(any(feature = "tokio", feature = "smol"))]
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        log::debug!("release undelivered lock {}", self.0);
+        let _ = unlock(self.0);
+    }
+}
+
+pub fn unlock(id: u64) -> io::Result<()> {
+    match locks().remove(&id) {
+        // closing the file descriptor releases the lock
+        Some(file) => drop(file),
+        None => {
+            let kind = io::ErrorKind::NotFound;
+            let msg = format!("cannot unlock {id}: not locked");
+            return Err(io::Error::new(kind, msg));
+        }
+    }
+
+    Ok(())
+}
+
+fn locks() -> MutexGuard<'static, BTreeMap<u64, File>> {
+    LOCKS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn readers() -> MutexGuard<'static, BTreeMap<PathBuf, Reader>> {
+    READERS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(unix)]
+fn flock(file: &File, mode: LockMode) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let operation = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+        LockMode::TryShared => libc::LOCK_SH | libc::LOCK_NB,
+        LockMode::TryExclusive => libc::LOCK_EX | libc::LOCK_NB,
+    };
+
+    loop {
+        // SAFETY: the file descriptor is owned by the given file
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+
+        let err = io::Error::last_os_error();
+
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn flock(_file: &File, _mode: LockMode) -> io::Result<()> {
+    let kind = io::ErrorKind::Unsupported;
+    Err(io::Error::new(kind, "cannot lock: only supported on Unix"))
+}
+
+#[cfg(target_os = "linux")]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    match renameat2(from, to, libc::RENAME_NOREPLACE) {
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {
+            log::debug!("renameat2 not supported, fall back to check then rename: {err}");
+            check_then_rename(from, to)
+        }
+        res => res,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    check_then_rename(from, to)
+}
+
+/// Portable, non-atomic fallback of [`RenameMode::NoReplace`].
+fn check_then_rename(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        let kind = io::ErrorKind::AlreadyExists;
+        let msg = format!("cannot rename to {}: destination exists", to.display());
+        return Err(io::Error::new(kind, msg));
+    }
+
+    fs::rename(from, to)
+}
+
+#[cfg(target_os = "linux")]
+fn rename_exchange(from: &Path, to: &Path) -> io::Result<()> {
+    renameat2(from, to, libc::RENAME_EXCHANGE)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_exchange(from: &Path, to: &Path) -> io::Result<()> {
+    let kind = io::ErrorKind::Unsupported;
+    let msg = format!(
+        "cannot exchange {} and {}: only supported on Linux",
+        from.display(),
+        to.display()
+    );
+    Err(io::Error::new(kind, msg))
+}
+
+#[cfg(target_os = "linux")]
+fn renameat2(from: &Path, to: &Path, flags: libc::c_uint) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+
+    // the raw system call is used since the libc wrapper is not
+    // available on every C library
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            flags,
+        )
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch(path: &Path) -> io::Result<u64> {
+    let kind = io::ErrorKind::Unsupported;
+    let msg = format!("cannot watch {}: only supported on Linux", path.display());
+    Err(io::Error::new(kind, msg))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn poll_events(id: u64, _timeout: std::time::Duration) -> io::Result<Vec<crate::io::FsEvent>> {
+    let kind = io::ErrorKind::Unsupported;
+    let msg = format!("cannot poll watcher {id}: only supported on Linux");
+    Err(io::Error::new(kind, msg))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn unwatch(id: u64) -> io::Result<()> {
+    let kind = io::ErrorKind::Unsupported;
+    let msg = format!("cannot stop watcher {id}: only supported on Linux");
+    Err(io::Error::new(kind, msg))
+}
@@ -0,0 +1,256 @@
+//! The thread pool-based, blocking filesystem runtime.
+//!
+//! Batch file requests ([`FsIo::CreateFiles`], [`FsIo::ReadFiles`],
+//! [`FsIo::ReadMetadatas`] and [`FsIo::RemoveFiles`]) are split into
+//! chunks, then processed in parallel by a pool of persistent worker
+//! threads. Other requests, including batch directory requests which
+//! may depend on each other, are processed serially by the [standard
+//! runtime].
+//!
+//! [standard runtime]: crate::runtimes::std
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use log::{debug, trace};
+
+use crate::io::{FsIo, FsMetadata};
+
+/// A unit of work sent to worker threads.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The thread pool-based filesystem runtime.
+///
+/// Holds worker threads, spawned once at creation and reused by
+/// every batch request. Workers are stopped when the runtime is
+/// dropped.
+#[derive(Debug)]
+pub struct ThreadPool {
+    threads: NonZeroUsize,
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Creates a new runtime using the given number of worker
+    /// threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a worker thread cannot be spawned, like
+    /// [`thread::spawn`].
+    pub fn new(threads: NonZeroUsize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.get())
+            .map(|_| {
+                let receiver = receiver.clone();
+
+                thread::spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(err) => err.into_inner().recv(),
+                    };
+
+                    // the sender is dropped with the runtime
+                    let Ok(job) = job else {
+                        break;
+                    };
+
+                    job();
+                })
+            })
+            .collect();
+
+        Self {
+            threads,
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Returns the number of worker threads.
+    pub fn threads(&self) -> NonZeroUsize {
+        self.threads
+    }
+
+    /// The thread pool-based filesystem runtime handler.
+    pub fn handle(&self, input: FsIo) -> io::Result<FsIo> {
+        match input {
+            FsIo::CreateFiles(input) => self.create_files(input),
+            FsIo::ReadFiles(input) => self.read_files(input),
+            FsIo::ReadMetadatas(input) => self.read_metadatas(input),
+            FsIo::RemoveFiles(input) => self.remove_files(input),
+            input => super::std::handle(input),
+        }
+    }
+
+    pub fn create_files(&self, input: Result<(), BTreeMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
+        let Err(contents) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file contents"));
+        };
+
+        self.fan_out(contents, |(path, contents)| fs::write(path, contents))?;
+
+        Ok(FsIo::CreateFiles(Ok(())))
+    }
+
+    pub fn read_files(
+        &self,
+        input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>,
+    ) -> io::Result<FsIo> {
+        let Err(paths) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file paths"));
+        };
+
+        let contents = self.fan_out(paths, |path| {
+            let contents = fs::read(&path)?;
+            Ok((path, contents))
+        })?;
+
+        Ok(FsIo::ReadFiles(Ok(contents.into_iter().collect())))
+    }
+
+    pub fn read_metadatas(
+        &self,
+        input: Result<BTreeMap<PathBuf, FsMetadata>, BTreeSet<PathBuf>>,
+    ) -> io::Result<FsIo> {
+        let Err(paths) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing entry paths"));
+        };
+
+        let metadatas = self.fan_out(paths, |path| {
+            let metadata = fs::symlink_metadata(&path)?;
+            Ok((path, metadata.into()))
+        })?;
+
+        Ok(FsIo::ReadMetadatas(Ok(metadatas.into_iter().collect())))
+    }
+
+    pub fn remove_files(&self, input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
+        let Err(paths) = input else {
+            let kind = io::ErrorKind::InvalidInput;
+            return Err(io::Error::new(kind, "missing file paths"));
+        };
+
+        self.fan_out(paths, fs::remove_file)?;
+
+        Ok(FsIo::RemoveFiles(Ok(())))
+    }
+
+    /// Applies the given function to all items, using as many
+    /// worker threads as configured.
+    ///
+    /// Items are split into contiguous chunks, one per worker, and
+    /// outputs are returned in the same order as items. Returns the
+    /// first error encountered, in item order. Workers stop
+    /// processing their chunk at their first error, other workers
+    /// still process theirs.
+    fn fan_out<T, R>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        f: impl Fn(T) -> io::Result<R> + Send + Sync + 'static,
+    ) -> io::Result<Vec<R>>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+    {
+        let items: Vec<T> = items.into_iter().collect();
+        let threads = self.threads.get().min(items.len());
+
+        if threads <= 1 {
+            return items.into_iter().map(f).collect();
+        }
+
+        let Some(jobs) = &self.jobs else {
+            return Err(io::Error::other("thread pool stopped"));
+        };
+
+        let chunk_size = items.len().div_ceil(threads);
+        let mut items = items.into_iter().peekable();
+        let mut chunks: Vec<Vec<T>> = Vec::with_capacity(threads);
+
+        while items.peek().is_some() {
+            chunks.push(items.by_ref().take(chunk_size).collect());
+        }
+
+        trace!("fan out {} chunks of {chunk_size} items", chunks.len());
+
+        let f = Arc::new(f);
+        let (sender, receiver) = mpsc::channel();
+        let n = chunks.len();
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let f = f.clone();
+            let sender = sender.clone();
+
+            let job = Box::new(move || {
+                let results = panic::catch_unwind(AssertUnwindSafe(|| {
+                    chunk.into_iter().map(&*f).collect::<io::Result<Vec<R>>>()
+                }));
+
+                // the receiver is only dropped after all chunks
+                let _ = sender.send((i, results));
+            });
+
+            if jobs.send(job).is_err() {
+                return Err(io::Error::other("thread pool stopped"));
+            }
+        }
+
+        let mut outputs: Vec<Option<io::Result<Vec<R>>>> = (0..n).map(|_| None).collect();
+
+        for _ in 0..n {
+            let Ok((i, results)) = receiver.recv() else {
+                return Err(io::Error::other("thread pool worker stopped"));
+            };
+
+            match results {
+                Ok(results) => outputs[i] = Some(results),
+                Err(panic) => panic::resume_unwind(panic),
+            }
+        }
+
+        let mut results = Vec::new();
+
+        for output in outputs.into_iter().flatten() {
+            results.extend(output?);
+        }
+
+        Ok(results)
+    }
+}
+
+impl Default for ThreadPool {
+    /// Creates a new runtime using as many worker threads as the
+    /// available parallelism, or a single thread if it cannot be
+    /// determined.
+    fn default() -> Self {
+        let threads = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+        Self::new(threads)
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // dropping the sender stops workers once their job is done
+        self.jobs.take();
+
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                debug!("thread pool worker panicked");
+            }
+        }
+    }
+}
@@ -1,14 +1,18 @@
 //! The Tokio-based, async filesystem runtime.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet},
     io,
     path::PathBuf,
+    time::{Duration, SystemTime},
 };
 
-use tokio::fs;
+use tokio::{fs, task};
 
-use crate::io::FsIo;
+use crate::{
+    io::{FsChunk, FsEvent, FsIo, FsMetadata, LockMode, RenameMode},
+    runtimes::{sort_by_depth, sys},
+};
 
 /// The Tokio-based, async filesystem runtime handler.
 ///
@@ -16,21 +20,51 @@ use crate::io::FsIo;
 /// module [`tokio::io`] to process [`FsIo`].
 pub async fn handle(input: FsIo) -> io::Result<FsIo> {
     match input {
+        FsIo::AppendFile(input) => append_file(input).await,
         FsIo::CreateDir(input) => create_dir(input).await,
         FsIo::CreateDirs(input) => create_dirs(input).await,
         FsIo::CreateFile(input) => create_file(input).await,
         FsIo::CreateFiles(input) => create_files(input).await,
+        FsIo::CreateNewFile(input) => create_new_file(input).await,
+        FsIo::Error(err) => {
+            let kind = io::ErrorKind::InvalidInput;
+            let msg = format!("cannot process error output: {err}");
+            Err(io::Error::new(kind, msg))
+        }
+        FsIo::Lock(input) => lock(input).await,
+        FsIo::PollEvents(input) => poll_events(input).await,
+        FsIo::ReadChunks(input) => read_chunks(input).await,
         FsIo::ReadDir(input) => read_dir(input).await,
         FsIo::ReadFile(input) => read_file(input).await,
         FsIo::ReadFiles(input) => read_files(input).await,
+        FsIo::ReadMetadata(input) => read_metadata(input).await,
+        FsIo::ReadMetadatas(input) => read_metadatas(input).await,
+        FsIo::ReadTargetMetadata(input) => read_target_metadata(input).await,
         FsIo::RemoveDir(input) => remove_dir(input).await,
         FsIo::RemoveDirs(input) => remove_dirs(input).await,
         FsIo::RemoveFile(input) => remove_file(input).await,
         FsIo::RemoveFiles(input) => remove_files(input).await,
         FsIo::Rename(input) => rename(input).await,
+        FsIo::SetModified(input) => set_modified(input).await,
+        FsIo::SyncDir(input) => sync_dir(input).await,
+        FsIo::SyncFile(input) => sync_file(input).await,
+        FsIo::Unlock(input) => unlock(input).await,
+        FsIo::Unwatch(input) => unwatch(input).await,
+        FsIo::Watch(input) => watch(input).await,
     }
 }
 
+pub async fn append_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    task::spawn_blocking(move || sys::append_file(&path, &contents)).await??;
+
+    Ok(FsIo::AppendFile(Ok(())))
+}
+
 pub async fn create_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
@@ -42,13 +76,14 @@ pub async fn create_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::CreateDir(Ok(())))
 }
 
-pub async fn create_dirs(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub async fn create_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory paths"));
     };
 
-    for path in paths {
+    // parents need to be created before their children
+    for path in sort_by_depth(paths) {
         fs::create_dir(path).await?;
     }
 
@@ -66,7 +101,7 @@ pub async fn create_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<Fs
     Ok(FsIo::CreateFile(Ok(())))
 }
 
-pub async fn create_files(input: Result<(), HashMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
+pub async fn create_files(input: Result<(), BTreeMap<PathBuf, Vec<u8>>>) -> io::Result<FsIo> {
     let Err(contents) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file contents"));
@@ -79,13 +114,68 @@ pub async fn create_files(input: Result<(), HashMap<PathBuf, Vec<u8>>>) -> io::R
     Ok(FsIo::CreateFiles(Ok(())))
 }
 
-pub async fn read_dir(input: Result<HashSet<PathBuf>, PathBuf>) -> io::Result<FsIo> {
+pub async fn create_new_file(input: Result<(), (PathBuf, Vec<u8>)>) -> io::Result<FsIo> {
+    let Err((path, contents)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path and contents"));
+    };
+
+    task::spawn_blocking(move || sys::create_new_file(&path, &contents)).await??;
+
+    Ok(FsIo::CreateNewFile(Ok(())))
+}
+
+pub async fn lock(input: Result<u64, (PathBuf, LockMode)>) -> io::Result<FsIo> {
+    let Err((path, mode)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let lock = move || sys::lock(&path, mode).map(sys::LockGuard::new);
+    let guard = task::spawn_blocking(lock).await??;
+
+    Ok(FsIo::Lock(Ok(guard.into_id())))
+}
+
+pub async fn poll_events(input: Result<Vec<FsEvent>, (u64, Duration)>) -> io::Result<FsIo> {
+    let Err((id, timeout)) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    let events = task::spawn_blocking(move || sys::poll_events(id, timeout)).await??;
+
+    Ok(FsIo::PollEvents(Ok(events)))
+}
+
+pub async fn read_chunks(
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeMap<PathBuf, FsChunk>>,
+) -> io::Result<FsIo> {
+    let Err(chunks) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file chunks"));
+    };
+
+    let mut contents = BTreeMap::new();
+
+    for (path, FsChunk { offset, len }) in chunks {
+        let chunk = {
+            let path = path.clone();
+            task::spawn_blocking(move || sys::read_chunk(&path, offset, len)).await??
+        };
+        contents.insert(path, chunk);
+    }
+
+    Ok(FsIo::ReadChunks(Ok(contents)))
+}
+
+pub async fn read_dir(input: Result<BTreeSet<PathBuf>, PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory path"));
     };
 
-    let mut paths = HashSet::new();
+    let mut paths = BTreeSet::new();
     let mut dir = fs::read_dir(path).await?;
 
     while let Some(entry) = dir.next_entry().await? {
@@ -107,14 +197,14 @@ pub async fn read_file(input: Result<Vec<u8>, PathBuf>) -> io::Result<FsIo> {
 }
 
 pub async fn read_files(
-    input: Result<HashMap<PathBuf, Vec<u8>>, HashSet<PathBuf>>,
+    input: Result<BTreeMap<PathBuf, Vec<u8>>, BTreeSet<PathBuf>>,
 ) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
     };
 
-    let mut contents = HashMap::new();
+    let mut contents = BTreeMap::new();
 
     for path in paths {
         let content = fs::read(&path).await?;
@@ -124,6 +214,46 @@ pub async fn read_files(
     Ok(FsIo::ReadFiles(Ok(contents)))
 }
 
+pub async fn read_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::symlink_metadata(path).await?;
+
+    Ok(FsIo::ReadMetadata(Ok(metadata.into())))
+}
+
+pub async fn read_metadatas(
+    input: Result<BTreeMap<PathBuf, FsMetadata>, BTreeSet<PathBuf>>,
+) -> io::Result<FsIo> {
+    let Err(paths) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry paths"));
+    };
+
+    let mut metadatas = BTreeMap::new();
+
+    for path in paths {
+        let metadata = fs::symlink_metadata(&path).await?;
+        metadatas.insert(path, metadata.into());
+    }
+
+    Ok(FsIo::ReadMetadatas(Ok(metadatas)))
+}
+
+pub async fn read_target_metadata(input: Result<FsMetadata, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing entry path"));
+    };
+
+    let metadata = fs::metadata(path).await?;
+
+    Ok(FsIo::ReadTargetMetadata(Ok(metadata.into())))
+}
+
 pub async fn remove_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     let Err(path) = input else {
         let kind = io::ErrorKind::InvalidInput;
@@ -135,13 +265,14 @@ pub async fn remove_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::RemoveDir(Ok(())))
 }
 
-pub async fn remove_dirs(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub async fn remove_dirs(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing directory paths"));
     };
 
-    for path in paths {
+    // children need to be removed before their parents
+    for path in sort_by_depth(paths).into_iter().rev() {
         fs::remove_dir_all(path).await?;
     }
 
@@ -159,7 +290,7 @@ pub async fn remove_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
     Ok(FsIo::RemoveFile(Ok(())))
 }
 
-pub async fn remove_files(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsIo> {
+pub async fn remove_files(input: Result<(), BTreeSet<PathBuf>>) -> io::Result<FsIo> {
     let Err(paths) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
@@ -172,15 +303,94 @@ pub async fn remove_files(input: Result<(), HashSet<PathBuf>>) -> io::Result<FsI
     Ok(FsIo::RemoveFiles(Ok(())))
 }
 
-pub async fn rename(input: Result<(), Vec<(PathBuf, PathBuf)>>) -> io::Result<FsIo> {
-    let Err(paths) = input else {
+pub async fn rename(input: Result<(), (Vec<(PathBuf, PathBuf)>, RenameMode)>) -> io::Result<FsIo> {
+    let Err((paths, mode)) = input else {
         let kind = io::ErrorKind::InvalidInput;
         return Err(io::Error::new(kind, "missing file paths"));
     };
 
     for (from, to) in paths {
-        fs::rename(from, to).await?;
+        match mode {
+            RenameMode::Replace => fs::rename(from, to).await?,
+            mode => task::spawn_blocking(move || sys::rename(&from, &to, mode)).await??,
+        }
     }
 
     Ok(FsIo::Rename(Ok(())))
 }
+
+pub async fn set_modified(input: Result<(), BTreeMap<PathBuf, SystemTime>>) -> io::Result<FsIo> {
+    let Err(times) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(
+            kind,
+            "missing file paths and modification times",
+        ));
+    };
+
+    task::spawn_blocking(move || {
+        for (path, time) in times {
+            sys::set_modified(&path, time)?;
+        }
+
+        io::Result::Ok(())
+    })
+    .await??;
+
+    Ok(FsIo::SetModified(Ok(())))
+}
+
+pub async fn sync_dir(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    task::spawn_blocking(move || sys::sync_dir(&path)).await??;
+
+    Ok(FsIo::SyncDir(Ok(())))
+}
+
+pub async fn sync_file(input: Result<(), PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing file path"));
+    };
+
+    task::spawn_blocking(move || sys::sync_file(&path)).await??;
+
+    Ok(FsIo::SyncFile(Ok(())))
+}
+
+pub async fn unlock(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing lock identifier"));
+    };
+
+    sys::unlock(id)?;
+
+    Ok(FsIo::Unlock(Ok(())))
+}
+
+pub async fn unwatch(input: Result<(), u64>) -> io::Result<FsIo> {
+    let Err(id) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing watcher identifier"));
+    };
+
+    sys::unwatch(id)?;
+
+    Ok(FsIo::Unwatch(Ok(())))
+}
+
+pub async fn watch(input: Result<u64, PathBuf>) -> io::Result<FsIo> {
+    let Err(path) = input else {
+        let kind = io::ErrorKind::InvalidInput;
+        return Err(io::Error::new(kind, "missing directory path"));
+    };
+
+    let id = task::spawn_blocking(move || sys::watch(&path)).await??;
+
+    Ok(FsIo::Watch(Ok(id)))
+}
@@ -0,0 +1,143 @@
+//! The recording and replaying filesystem runtimes.
+//!
+//! [`Record`] wraps another runtime handler and stores every I/O
+//! request and response exchanged with a coroutine into a
+//! transcript. [`Replay`] serves a transcript back, without touching
+//! the filesystem at all. This is useful to reproduce bugs or to
+//! write coroutine tests as golden transcripts.
+//!
+//! ```rust,ignore
+//! use io_fs::runtimes::{std::handle, transcript::{Record, Replay}};
+//!
+//! let mut record = Record::new();
+//!
+//! // blocking runtimes can be wrapped directly
+//! let output = record.handle(io, handle)?;
+//!
+//! // async runtimes need to record the request first
+//! let output = record.record(io.clone(), io_fs::runtimes::tokio::handle(io).await)?;
+//!
+//! // the transcript can then be served back
+//! let mut replay = Replay::new(record.into_transcript());
+//! let output = replay.handle(io)?;
+//! ```
+
+use std::{collections::VecDeque, io};
+
+use log::debug;
+
+use crate::{error::FsIoError, io::FsIo};
+
+/// A single I/O exchange between a coroutine and a runtime.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TranscriptEntry {
+    /// The I/O request emitted by the coroutine.
+    pub request: FsIo,
+
+    /// The response returned by the runtime.
+    pub response: Result<FsIo, FsIoError>,
+}
+
+/// The recording filesystem runtime.
+///
+/// Holds the transcript of I/O exchanges recorded so far.
+#[derive(Clone, Debug, Default)]
+pub struct Record {
+    transcript: Vec<TranscriptEntry>,
+}
+
+impl Record {
+    /// Creates a new recording runtime with an empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the I/O exchanges recorded so far, in order.
+    pub fn transcript(&self) -> &[TranscriptEntry] {
+        &self.transcript
+    }
+
+    /// Consumes the runtime and returns the recorded I/O exchanges.
+    pub fn into_transcript(self) -> Vec<TranscriptEntry> {
+        self.transcript
+    }
+
+    /// Records the given I/O request and its response.
+    ///
+    /// The response is given back untouched, so that it can be
+    /// passed to the coroutine.
+    pub fn record(&mut self, request: FsIo, response: io::Result<FsIo>) -> io::Result<FsIo> {
+        debug!("record {request:?} from recording runtime");
+
+        let entry = TranscriptEntry {
+            request,
+            response: match &response {
+                Ok(output) => Ok(output.clone()),
+                Err(err) => Err(err.into()),
+            },
+        };
+
+        self.transcript.push(entry);
+        response
+    }
+
+    /// The recording filesystem runtime handler.
+    ///
+    /// Forwards the request to the given blocking runtime `handler`,
+    /// then records the exchange (see [`Record::record`]).
+    pub fn handle(
+        &mut self,
+        input: FsIo,
+        handler: impl FnOnce(FsIo) -> io::Result<FsIo>,
+    ) -> io::Result<FsIo> {
+        let request = input.clone();
+        let response = handler(input);
+        self.record(request, response)
+    }
+}
+
+/// The replaying filesystem runtime.
+///
+/// Holds the I/O exchanges that remain to be served.
+#[derive(Clone, Debug, Default)]
+pub struct Replay {
+    transcript: VecDeque<TranscriptEntry>,
+}
+
+impl Replay {
+    /// Creates a new replaying runtime from the given transcript.
+    pub fn new(transcript: impl IntoIterator<Item = TranscriptEntry>) -> Self {
+        let transcript = transcript.into_iter().collect();
+        Self { transcript }
+    }
+
+    /// Returns `true` if the whole transcript has been served.
+    pub fn is_done(&self) -> bool {
+        self.transcript.is_empty()
+    }
+
+    /// The replaying filesystem runtime handler.
+    ///
+    /// Serves the next recorded response if the given request
+    /// matches the next recorded one, otherwise returns an error of
+    /// kind [`io::ErrorKind::InvalidData`].
+    pub fn handle(&mut self, input: FsIo) -> io::Result<FsIo> {
+        let Some(entry) = self.transcript.pop_front() else {
+            let kind = io::ErrorKind::UnexpectedEof;
+            let msg = format!("cannot replay {input:?}: transcript exhausted");
+            return Err(io::Error::new(kind, msg));
+        };
+
+        if entry.request != input {
+            let kind = io::ErrorKind::InvalidData;
+            let expected = entry.request;
+            let msg = format!("cannot replay {input:?}: expected {expected:?}");
+            return Err(io::Error::new(kind, msg));
+        }
+
+        debug!("replay {input:?} from replaying runtime");
+
+        Ok(entry.response?)
+    }
+}
@@ -0,0 +1,182 @@
+#![cfg(all(feature = "io-uring", target_os = "linux"))]
+
+use std::{collections::BTreeMap, io, mem::MaybeUninit};
+
+use io_fs::{
+    coroutines::{
+        create_dir::CreateDir, create_files::CreateFiles, read_files::ReadFiles,
+        remove_files::RemoveFiles,
+    },
+    error::FsResult,
+    runtimes::io_uring::IoUring,
+};
+use tempfile::tempdir;
+
+#[test]
+fn io_uring() {
+    let _ = env_logger::try_init();
+
+    // io_uring can be disabled by the kernel or by seccomp filters
+    let mut runtime = match IoUring::with_entries(4) {
+        Ok(runtime) => runtime,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return,
+        Err(err) => panic!("{err}"),
+    };
+
+    let workdir = tempdir().unwrap();
+
+    // create single directory, using the standard runtime
+
+    let mut arg = None;
+    let mut coroutine = CreateDir::new(workdir.path().join("dir1"));
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    }
+
+    assert!(workdir.path().join("dir1").is_dir());
+
+    // create more files than queue entries
+
+    let paths: Vec<_> = (0..10)
+        .map(|n| workdir.path().join("dir1").join(format!("file{n}")))
+        .collect();
+
+    let mut arg = None;
+    let mut coroutine = CreateFiles::new(
+        paths
+            .iter()
+            .enumerate()
+            .map(|(n, path)| (path, format!("file{n}").into_bytes())),
+    );
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    }
+
+    for (n, path) in paths.iter().enumerate() {
+        assert_eq!(format!("file{n}").as_bytes(), std::fs::read(path).unwrap());
+    }
+
+    // read multiple files
+
+    let mut arg = None;
+    let mut coroutine = ReadFiles::new(&paths);
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    };
+
+    let expected_contents: BTreeMap<_, _> = paths
+        .iter()
+        .enumerate()
+        .map(|(n, path)| (path.clone(), format!("file{n}").into_bytes()))
+        .collect();
+
+    assert_eq!(contents, expected_contents);
+
+    // read missing files
+
+    let mut coroutine = ReadFiles::new([workdir.path().join("file0")]);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read files I/O request");
+    };
+
+    let err = runtime.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+
+    // remove multiple files
+
+    let mut arg = None;
+    let mut coroutine = RemoveFiles::new(paths.clone());
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    }
+
+    for path in &paths {
+        assert!(!path.exists());
+    }
+}
+
+#[test]
+fn io_uring_open_files_limit() {
+    let _ = env_logger::try_init();
+
+    // lower the open files limit below the batch size
+    let mut limit = MaybeUninit::<libc::rlimit>::zeroed();
+    assert_eq!(0, unsafe {
+        libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr())
+    });
+    let mut limit = unsafe { limit.assume_init() };
+    limit.rlim_cur = 64;
+    assert_eq!(0, unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) });
+
+    let mut runtime = match IoUring::with_entries(256) {
+        Ok(runtime) => runtime,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return,
+        Err(err) => panic!("{err}"),
+    };
+
+    let workdir = tempdir().unwrap();
+
+    // create more files than the open files limit
+
+    let paths: Vec<_> = (0..300)
+        .map(|n| workdir.path().join(format!("file{n}")))
+        .collect();
+
+    let mut arg = None;
+    let mut coroutine = CreateFiles::new(
+        paths
+            .iter()
+            .enumerate()
+            .map(|(n, path)| (path, format!("file{n}").into_bytes())),
+    );
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    }
+
+    // read more files than the open files limit
+
+    let mut arg = None;
+    let mut coroutine = ReadFiles::new(&paths);
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(paths.len(), contents.len());
+
+    for (n, path) in paths.iter().enumerate() {
+        assert_eq!(format!("file{n}").as_bytes(), contents[path]);
+    }
+}
@@ -0,0 +1,145 @@
+#![cfg(all(feature = "remote", feature = "std", unix))]
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    io,
+    os::unix::{ffi::OsStrExt, net::UnixStream},
+    thread,
+};
+
+use io_fs::{
+    coroutines::{create_files::CreateFiles, read_dir::ReadDir, read_file::ReadFile},
+    error::FsResult,
+    runtimes::{
+        read_only,
+        remote::{serve, Client},
+        std::handle,
+    },
+};
+use tempfile::tempdir;
+
+#[test]
+fn remote() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let (client, server) = UnixStream::pair().unwrap();
+
+    let server = thread::spawn(move || {
+        let reader = server.try_clone().unwrap();
+        serve(reader, server, handle)
+    });
+
+    let mut client = Client::new(client.try_clone().unwrap(), client);
+
+    // create multiple files
+
+    let mut arg = None;
+    let mut coroutine = CreateFiles::new([
+        (workdir.path().join("file1"), *b"file1"),
+        (workdir.path().join("file2"), *b"file2"),
+    ]);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(client.handle(io).unwrap()),
+        }
+    }
+
+    assert!(workdir.path().join("file1").is_file());
+    assert!(workdir.path().join("file2").is_file());
+
+    // read directory
+
+    let mut arg = None;
+    let mut coroutine = ReadDir::new(workdir.path());
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(client.handle(io).unwrap()),
+        }
+    };
+
+    let expected_paths =
+        BTreeSet::from_iter([workdir.path().join("file1"), workdir.path().join("file2")]);
+
+    assert_eq!(paths, expected_paths);
+
+    // runtime errors are sent back to the client
+
+    let mut coroutine = ReadFile::new(workdir.path().join("file3"));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read file I/O request");
+    };
+
+    let err = client.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+
+    // unserializable outputs are sent back as errors
+
+    let path = workdir.path().join(OsStr::from_bytes(b"file\xff"));
+    std::fs::write(&path, b"").unwrap();
+
+    let mut coroutine = ReadDir::new(workdir.path());
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read directory I/O request");
+    };
+
+    let err = client.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+    std::fs::remove_file(&path).unwrap();
+
+    // the server keeps processing requests
+
+    let mut arg = None;
+    let mut coroutine = ReadFile::new(workdir.path().join("file1"));
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(client.handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(b"file1", contents.as_slice());
+
+    // closing the client stops the server
+
+    drop(client);
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn remote_read_only() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let (client, server) = UnixStream::pair().unwrap();
+
+    thread::spawn(move || {
+        let reader = server.try_clone().unwrap();
+        serve(reader, server, |io| read_only::handle(io, handle))
+    });
+
+    let mut client = Client::new(client.try_clone().unwrap(), client);
+
+    let mut coroutine = CreateFiles::new([(workdir.path().join("file1"), *b"file1")]);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected create files I/O request");
+    };
+
+    let err = client.handle(io).unwrap_err();
+
+    assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    assert!(!workdir.path().join("file1").exists());
+}
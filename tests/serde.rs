@@ -0,0 +1,77 @@
+#![cfg(feature = "serde")]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::PathBuf,
+};
+
+use io_fs::{
+    error::{FsError, FsIoError, FsResult},
+    io::{FsIo, RenameMode},
+    runtimes::transcript::TranscriptEntry,
+};
+
+#[test]
+fn serde() {
+    // I/O requests and responses
+
+    let inputs = [
+        FsIo::CreateFile(Err((PathBuf::from("/tmp/file1"), b"file1".to_vec()))),
+        FsIo::ReadDir(Ok(BTreeSet::from_iter([PathBuf::from("/tmp/dir1")]))),
+        FsIo::ReadFiles(Ok(BTreeMap::from_iter([(
+            PathBuf::from("/tmp/file2"),
+            b"file2".to_vec(),
+        )]))),
+        FsIo::Rename(Err((
+            vec![(PathBuf::from("/tmp/file3"), PathBuf::from("/tmp/file4"))],
+            RenameMode::NoReplace,
+        ))),
+    ];
+
+    for input in inputs {
+        let json = serde_json::to_string(&input).unwrap();
+        let output: FsIo = serde_json::from_str(&json).unwrap();
+        assert_eq!(input, output);
+    }
+
+    // coroutine results
+
+    let input: FsResult<Vec<u8>> = FsResult::Io(FsIo::ReadFile(Err("/tmp/file1".into())));
+    let json = serde_json::to_string(&input).unwrap();
+    let output: FsResult<Vec<u8>> = serde_json::from_str(&json).unwrap();
+
+    let FsResult::Io(FsIo::ReadFile(Err(path))) = output else {
+        panic!("expected read file I/O request, got {output:?}");
+    };
+
+    assert_eq!(PathBuf::from("/tmp/file1"), path);
+
+    let input: FsResult = FsResult::Err(FsError::InvalidArgument(
+        "read file output".into(),
+        FsIo::CreateDir(Ok(())),
+    ));
+    let json = serde_json::to_string(&input).unwrap();
+    let output: FsResult = serde_json::from_str(&json).unwrap();
+
+    let FsResult::Err(FsError::InvalidArgument(expected, FsIo::CreateDir(Ok(())))) = output else {
+        panic!("expected invalid argument error, got {output:?}");
+    };
+
+    assert_eq!("read file output", expected);
+
+    // transcripts
+
+    let input = vec![TranscriptEntry {
+        request: FsIo::ReadFile(Err("/tmp/file1".into())),
+        response: Err(FsIoError {
+            kind: io::ErrorKind::NotFound,
+            message: "No such file or directory".into(),
+        }),
+    }];
+
+    let json = serde_json::to_string(&input).unwrap();
+    let output: Vec<TranscriptEntry> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(input, output);
+}
@@ -1,16 +1,53 @@
 #![cfg(feature = "std")]
+#![allow(clippy::bool_assert_comparison)]
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 use io_fs::{
     coroutines::{
-        create_dir::CreateDir, create_dirs::CreateDirs, create_file::CreateFile,
-        create_files::CreateFiles, read_dir::ReadDir, read_file::ReadFile, read_files::ReadFiles,
-        remove_dir::RemoveDir, remove_dirs::RemoveDirs, remove_file::RemoveFile,
-        remove_files::RemoveFiles, rename::Rename,
+        create_dir::CreateDir,
+        create_dirs::CreateDirs,
+        create_file::CreateFile,
+        create_files::CreateFiles,
+        detect_changes::{DetectChanges, Snapshot},
+        diff_trees::{Compare, DiffTrees},
+        disk_usage::{DiskUsage, Usage},
+        glob::Glob,
+        ignore::{IgnoreRules, GITIGNORE},
+        lock::Lock,
+        lock_file::LockFile,
+        maildir::{self, CreateMaildir, DeliverMessage, MoveToCur, SetFlags},
+        mbox::{AppendMbox, MboxMessage, ReadMbox},
+        mirror::{Mirror, MirrorAction},
+        read_dir::ReadDir,
+        read_dir_sorted::{ReadDirSorted, SortKey},
+        read_file::ReadFile,
+        read_files::ReadFiles,
+        read_metadata::ReadMetadata,
+        remove_dir::RemoveDir,
+        remove_dirs::RemoveDirs,
+        remove_file::RemoveFile,
+        remove_files::RemoveFiles,
+        rename::Rename,
+        transactional_rename::TransactionalRename,
+        unlock::Unlock,
+        vdir::{CollectionMeta, ListItems, ReadMeta, WriteItem, WriteMeta},
+        walk::Walk,
+    },
+    error::{FsError, FsResult},
+    io::{FsIo, FsKind, LockMode, RenameMode},
+    runtimes::{
+        dry_run::{DryRun, FsMutation},
+        read_only::{self, ReadOnly},
+        std::handle,
+        thread_pool::ThreadPool,
+        transcript::{Record, Replay},
     },
-    error::FsResult,
-    runtimes::std::handle,
 };
 use tempfile::tempdir;
 
@@ -98,7 +135,7 @@ fn std() {
         }
     };
 
-    let expected_paths = HashSet::from_iter([workdir.path().join("dir1").join("file1")]);
+    let expected_paths = BTreeSet::from_iter([workdir.path().join("dir1").join("file1")]);
 
     assert_eq!(paths, expected_paths);
 
@@ -113,7 +150,7 @@ fn std() {
         }
     };
 
-    let expected_paths = HashSet::from_iter([
+    let expected_paths = BTreeSet::from_iter([
         workdir.path().join("dir2").join("file2"),
         workdir.path().join("dir2").join("file3"),
     ]);
@@ -164,7 +201,7 @@ fn std() {
         }
     };
 
-    let expected_contents = HashMap::from_iter([
+    let expected_contents = BTreeMap::from_iter([
         (workdir.path().join("dir2").join("file2"), b"file2".to_vec()),
         (workdir.path().join("dir2").join("file3"), b"file3".to_vec()),
     ]);
@@ -255,3 +292,1914 @@ fn std() {
     assert_eq!(false, workdir.path().join("dir1").is_dir());
     assert_eq!(false, workdir.path().join("dir2").is_dir());
 }
+
+#[test]
+fn read_only() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    std::fs::write(workdir.path().join("file1"), b"file1").unwrap();
+
+    // read requests pass through
+
+    let mut arg = None;
+    let mut coroutine = ReadFile::new(workdir.path().join("file1"));
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(read_only::handle(io, handle).unwrap()),
+        }
+    };
+
+    assert_eq!(b"file1", contents.as_slice());
+
+    // mutating requests are denied
+
+    let mut coroutine = CreateDir::new(workdir.path().join("dir1"));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected create dir I/O request");
+    };
+
+    let err = read_only::handle(io, handle).unwrap_err();
+
+    assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    assert!(!workdir.path().join("dir1").exists());
+
+    let mut coroutine = Rename::new(Some((
+        workdir.path().join("file1"),
+        workdir.path().join("file2"),
+    )));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected rename I/O request");
+    };
+
+    let err = read_only::handle(io, handle).unwrap_err();
+
+    assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    assert!(workdir.path().join("file1").is_file());
+    assert!(!workdir.path().join("file2").exists());
+
+    // the wrapper type checks requests as well
+
+    let mut runtime = ReadOnly::new(handle);
+    let mut coroutine = CreateDir::new(workdir.path().join("dir1"));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected create dir I/O request");
+    };
+
+    let err = runtime.handle(io).unwrap_err();
+
+    assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    assert!(!workdir.path().join("dir1").exists());
+}
+
+#[test]
+fn dry_run() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    std::fs::write(workdir.path().join("file1"), b"file1").unwrap();
+
+    let mut dry_run = DryRun::new();
+
+    // mutating requests are recorded
+
+    let mut arg = None;
+    let mut coroutine = CreateFiles::new([(workdir.path().join("file2"), *b"file2")]);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(dry_run.handle(io, handle).unwrap()),
+        }
+    }
+
+    let mut arg = None;
+    let mut coroutine = Rename::new(Some((
+        workdir.path().join("file1"),
+        workdir.path().join("file3"),
+    )));
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(dry_run.handle(io, handle).unwrap()),
+        }
+    }
+
+    assert!(!workdir.path().join("file2").exists());
+    assert!(workdir.path().join("file1").is_file());
+    assert!(!workdir.path().join("file3").exists());
+
+    // read requests pass through
+
+    let mut arg = None;
+    let mut coroutine = ReadDir::new(workdir.path());
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(dry_run.handle(io, handle).unwrap()),
+        }
+    };
+
+    assert_eq!(paths, BTreeSet::from_iter([workdir.path().join("file1")]));
+
+    let expected_plan = vec![
+        FsMutation::CreateFile(workdir.path().join("file2"), b"file2".to_vec()),
+        FsMutation::Rename(workdir.path().join("file1"), workdir.path().join("file3")),
+    ];
+
+    assert_eq!(dry_run.into_plan(), expected_plan);
+}
+
+#[test]
+fn transcript() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let file1 = workdir.path().join("file1");
+    let file2 = workdir.path().join("file2");
+
+    // record I/O exchanges against the real filesystem
+
+    let mut record = Record::new();
+
+    let mut arg = None;
+    let mut coroutine = CreateFile::new(&file1, *b"file1");
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(record.handle(io, handle).unwrap()),
+        }
+    }
+
+    let mut arg = None;
+    let mut coroutine = ReadFile::new(&file1);
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(record.handle(io, handle).unwrap()),
+        }
+    };
+
+    assert_eq!(b"file1", contents.as_slice());
+
+    let mut coroutine = ReadFile::new(&file2);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read file I/O request");
+    };
+
+    let err = record.handle(io, handle).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+
+    let transcript = record.into_transcript();
+    assert_eq!(3, transcript.len());
+
+    // replay I/O exchanges without the filesystem
+
+    drop(workdir);
+
+    let mut replay = Replay::new(transcript);
+
+    let mut arg = None;
+    let mut coroutine = CreateFile::new(&file1, *b"file1");
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(replay.handle(io).unwrap()),
+        }
+    }
+
+    let mut arg = None;
+    let mut coroutine = ReadFile::new(&file1);
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(replay.handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(b"file1", contents.as_slice());
+
+    // requests not matching the transcript are rejected
+
+    let mut coroutine = ReadFile::new(&file1);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read file I/O request");
+    };
+
+    let err = replay.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert!(replay.is_done());
+}
+
+#[test]
+fn thread_pool() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let runtime = ThreadPool::new(NonZeroUsize::new(4).unwrap());
+
+    let paths: Vec<_> = (0..10)
+        .map(|n| workdir.path().join(format!("file{n}")))
+        .collect();
+
+    // create multiple files
+
+    let mut arg = None;
+    let mut coroutine = CreateFiles::new(
+        paths
+            .iter()
+            .enumerate()
+            .map(|(n, path)| (path, format!("file{n}").into_bytes())),
+    );
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    }
+
+    // read multiple files
+
+    let mut arg = None;
+    let mut coroutine = ReadFiles::new(&paths);
+
+    let contents = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(contents) => break contents,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle(io).unwrap()),
+        }
+    };
+
+    let expected_contents: BTreeMap<_, _> = paths
+        .iter()
+        .enumerate()
+        .map(|(n, path)| (path.clone(), format!("file{n}").into_bytes()))
+        .collect();
+
+    assert_eq!(contents, expected_contents);
+
+    // errors from any worker are returned
+
+    let mut coroutine =
+        RemoveFiles::new(paths.iter().cloned().chain([workdir.path().join("file10")]));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected remove files I/O request");
+    };
+
+    let err = runtime.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+
+    // the first error in path order is returned
+
+    std::fs::create_dir(workdir.path().join("a-dir")).unwrap();
+
+    let mut coroutine = ReadFiles::new(
+        [workdir.path().join("a-dir")]
+            .into_iter()
+            .chain(paths.iter().cloned())
+            .chain([workdir.path().join("z-missing")]),
+    );
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected read files I/O request");
+    };
+
+    let err = runtime.handle(io).unwrap_err();
+    assert_eq!(io::ErrorKind::IsADirectory, err.kind());
+}
+
+#[test]
+fn read_dir_sorted() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    std::fs::write(workdir.path().join("a"), b"aaa").unwrap();
+    std::fs::write(workdir.path().join("b"), b"b").unwrap();
+    std::fs::write(workdir.path().join("c"), b"cc").unwrap();
+    std::fs::create_dir(workdir.path().join("d")).unwrap();
+
+    // read metadata
+
+    let mut arg = None;
+    let mut coroutine = ReadMetadata::new(workdir.path().join("a"));
+
+    let metadata = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(metadata) => break metadata,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(FsKind::File, metadata.kind);
+    assert_eq!(3, metadata.len);
+
+    // sort by name
+
+    let mut arg = None;
+    let mut coroutine = ReadDirSorted::new(workdir.path(), SortKey::Name);
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    let expected_paths: Vec<_> = ["a", "b", "c", "d"]
+        .into_iter()
+        .map(|name| workdir.path().join(name))
+        .collect();
+
+    assert_eq!(paths, expected_paths);
+
+    // sort by size, directory size depends on the filesystem
+
+    let mut arg = None;
+    let mut coroutine = ReadDirSorted::new(workdir.path(), SortKey::Size);
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    let files: Vec<_> = paths.into_iter().filter(|path| path.is_file()).collect();
+
+    let expected_files: Vec<_> = ["b", "c", "a"]
+        .into_iter()
+        .map(|name| workdir.path().join(name))
+        .collect();
+
+    assert_eq!(files, expected_files);
+}
+
+#[test]
+fn nested_dirs() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let a = workdir.path().join("a");
+    let b = a.join("b");
+    let c = b.join("c");
+
+    // parents are created first
+
+    let mut arg = None;
+    let mut coroutine = CreateDirs::new([c.clone(), a.clone(), b.clone()]);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    assert!(c.is_dir());
+
+    // children are removed first
+
+    let mut arg = None;
+    let mut coroutine = RemoveDirs::new([a.clone(), c.clone()]);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    assert!(!a.exists());
+}
+
+#[test]
+fn rename_modes() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let file1 = workdir.path().join("file1");
+    let file2 = workdir.path().join("file2");
+    std::fs::write(&file1, b"file1").unwrap();
+    std::fs::write(&file2, b"file2").unwrap();
+
+    // no-replace fails when the destination exists
+
+    let mut coroutine = Rename::new([(&file1, &file2)]).with_mode(RenameMode::NoReplace);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected rename I/O request");
+    };
+
+    let err = handle(io).unwrap_err();
+
+    assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+    assert_eq!(b"file1", std::fs::read(&file1).unwrap().as_slice());
+    assert_eq!(b"file2", std::fs::read(&file2).unwrap().as_slice());
+
+    // exchange swaps both paths
+
+    if cfg!(target_os = "linux") {
+        let mut arg = None;
+        let mut coroutine = Rename::new([(&file1, &file2)]).with_mode(RenameMode::Exchange);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(()) => break,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+
+        assert_eq!(b"file2", std::fs::read(&file1).unwrap().as_slice());
+        assert_eq!(b"file1", std::fs::read(&file2).unwrap().as_slice());
+    }
+
+    // no-replace succeeds when the destination does not exist
+
+    let file3 = workdir.path().join("file3");
+
+    let mut arg = None;
+    let mut coroutine = Rename::new([(&file1, &file3)]).with_mode(RenameMode::NoReplace);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    assert!(!file1.exists());
+    assert!(file3.is_file());
+}
+
+#[test]
+fn transactional_rename() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let file1 = workdir.path().join("file1");
+    let file2 = workdir.path().join("file2");
+    let file3 = workdir.path().join("file3");
+    let file4 = workdir.path().join("file4");
+    std::fs::write(&file1, b"file1").unwrap();
+    std::fs::write(&file2, b"file2").unwrap();
+    std::fs::write(&file3, b"file3").unwrap();
+
+    // swaps and chains are applied as if simultaneous
+
+    let mut arg = None;
+    let mut coroutine =
+        TransactionalRename::new([(&file1, &file2), (&file2, &file1), (&file3, &file4)]);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+        }
+    }
+
+    assert_eq!(b"file2", std::fs::read(&file1).unwrap().as_slice());
+    assert_eq!(b"file1", std::fs::read(&file2).unwrap().as_slice());
+    assert_eq!(b"file3", std::fs::read(&file4).unwrap().as_slice());
+    assert!(!file3.exists());
+    assert_eq!(3, std::fs::read_dir(workdir.path()).unwrap().count());
+
+    // applied renames are undone when one fails
+
+    std::fs::write(&file3, b"file3").unwrap();
+
+    let mut arg = None;
+    let mut coroutine =
+        TransactionalRename::new([(&file1, &file2), (&file2, &file1), (&file4, &file3)]);
+
+    let err = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => panic!("expected rename error"),
+            FsResult::Err(err) => break err,
+            FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+        }
+    };
+
+    let FsError::Io(err) = err else {
+        panic!("expected I/O error, got {err:?}");
+    };
+
+    assert_eq!(io::ErrorKind::AlreadyExists, err.kind);
+    assert_eq!(b"file2", std::fs::read(&file1).unwrap().as_slice());
+    assert_eq!(b"file1", std::fs::read(&file2).unwrap().as_slice());
+    assert_eq!(b"file3", std::fs::read(&file3).unwrap().as_slice());
+    assert_eq!(b"file3", std::fs::read(&file4).unwrap().as_slice());
+    assert_eq!(4, std::fs::read_dir(workdir.path()).unwrap().count());
+
+    // coroutines that cannot recover fail with the I/O error
+
+    let mut arg = None;
+    let mut coroutine = ReadFile::new(workdir.path().join("file5"));
+
+    let err = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(_) => panic!("expected read error"),
+            FsResult::Err(err) => break err,
+            FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+        }
+    };
+
+    let FsError::Io(err) = err else {
+        panic!("expected I/O error, got {err:?}");
+    };
+
+    assert_eq!(io::ErrorKind::NotFound, err.kind);
+}
+
+#[test]
+fn diff_trees() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let old = workdir.path().join("old");
+    let new = workdir.path().join("new");
+
+    std::fs::create_dir_all(old.join("dir")).unwrap();
+    std::fs::write(old.join("same"), b"same").unwrap();
+    std::fs::write(old.join("edited"), b"old").unwrap();
+    std::fs::write(old.join("dir/moved"), b"moved!").unwrap();
+    std::fs::write(old.join("gone"), b"gone").unwrap();
+
+    std::fs::create_dir_all(new.join("sub")).unwrap();
+    std::fs::write(new.join("same"), b"same").unwrap();
+    std::fs::write(new.join("edited"), b"new").unwrap();
+    std::fs::write(new.join("sub/renamed"), b"moved!").unwrap();
+    std::fs::write(new.join("added"), b"added").unwrap();
+
+    // compare by content
+
+    let mut arg = None;
+    let mut coroutine = DiffTrees::new(&old, &new).with_compare(Compare::Content);
+
+    let diff = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(diff) => break diff,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    let added: BTreeSet<_> = ["added", "sub"].into_iter().map(PathBuf::from).collect();
+    assert_eq!(added, diff.added);
+
+    let removed: BTreeSet<_> = ["dir", "gone"].into_iter().map(PathBuf::from).collect();
+    assert_eq!(removed, diff.removed);
+
+    let modified: BTreeSet<_> = [PathBuf::from("edited")].into_iter().collect();
+    assert_eq!(modified, diff.modified);
+
+    let moved: BTreeMap<_, _> = [(PathBuf::from("dir/moved"), PathBuf::from("sub/renamed"))]
+        .into_iter()
+        .collect();
+    assert_eq!(moved, diff.moved);
+
+    // files are compared by chunks, each file being read once
+
+    let mut arg = None;
+    let mut read = BTreeMap::<PathBuf, u64>::new();
+    let mut coroutine = DiffTrees::new(&old, &new)
+        .with_compare(Compare::Content)
+        .with_batch_size(1);
+
+    let batched_diff = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(diff) => break diff,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => {
+                if let FsIo::ReadChunks(Err(chunks)) = &io {
+                    for (path, chunk) in chunks {
+                        assert_eq!(1, chunk.len, "too many bytes read at once");
+
+                        let offset = read.entry(path.clone()).or_default();
+                        assert_eq!(*offset, chunk.offset, "file read twice");
+                        *offset += chunk.len;
+                    }
+                }
+
+                arg = Some(handle(io).unwrap());
+            }
+        }
+    };
+
+    assert_eq!(diff, batched_diff);
+
+    // "edited" files differ from their first byte, so they are not
+    // read any further
+    assert_eq!(1, read[&old.join("edited")]);
+    assert_eq!(1, read[&new.join("edited")]);
+    assert_eq!(6, read[&old.join("dir/moved")]);
+    assert_eq!(6, read[&new.join("sub/renamed")]);
+
+    // compare by size and modification time
+
+    let mut arg = None;
+    let mut coroutine = DiffTrees::new(&old, &old);
+
+    let diff = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(diff) => break diff,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn mirror() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let source = workdir.path().join("source");
+    let destination = workdir.path().join("destination");
+
+    std::fs::create_dir_all(source.join("dir/sub")).unwrap();
+    std::fs::create_dir_all(source.join("conflict")).unwrap();
+    std::fs::write(source.join("same"), b"same").unwrap();
+    std::fs::write(source.join("edited"), b"new").unwrap();
+    std::fs::write(source.join("dir/sub/moved"), b"moved!").unwrap();
+    std::fs::write(source.join("conflict/file"), b"file").unwrap();
+
+    std::fs::create_dir_all(destination.join("old")).unwrap();
+    std::fs::write(destination.join("same"), b"same").unwrap();
+    std::fs::write(destination.join("edited"), b"old").unwrap();
+    std::fs::write(destination.join("old/moved"), b"moved!").unwrap();
+    std::fs::write(destination.join("conflict"), b"conflict").unwrap();
+    std::fs::write(destination.join("extraneous"), b"extraneous").unwrap();
+
+    // dry run only plans actions
+
+    let mut arg = None;
+    let mut coroutine = Mirror::new(&source, &destination)
+        .with_compare(Compare::Content)
+        .with_delete(true)
+        .with_dry_run(true);
+
+    let planned = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(actions) => break actions,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(read_only::handle(io, handle).unwrap()),
+        }
+    };
+
+    let expected = vec![
+        MirrorAction::RemoveFile("conflict".into()),
+        MirrorAction::RemoveFile("extraneous".into()),
+        MirrorAction::CreateDir("conflict".into()),
+        MirrorAction::CreateDir("dir".into()),
+        MirrorAction::CreateDir("dir/sub".into()),
+        MirrorAction::Move("old/moved".into(), "dir/sub/moved".into()),
+        MirrorAction::RemoveDir("old".into()),
+        MirrorAction::CopyFile("conflict/file".into()),
+        MirrorAction::CopyFile("edited".into()),
+    ];
+
+    assert_eq!(expected, planned);
+    assert!(destination.join("extraneous").is_file());
+
+    // actions are applied
+
+    let mut arg = None;
+    let mut coroutine = Mirror::new(&source, &destination)
+        .with_compare(Compare::Content)
+        .with_delete(true);
+
+    let actions = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(actions) => break actions,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(expected, actions);
+
+    let mut arg = None;
+    let mut coroutine = DiffTrees::new(&source, &destination).with_compare(Compare::Content);
+
+    let diff = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(diff) => break diff,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert!(diff.is_empty());
+
+    // copies keep source modification times, so that mirroring
+    // again with the default compare does nothing
+
+    let copy = workdir.path().join("copy");
+    std::fs::create_dir(&copy).unwrap();
+
+    for n in 0..2 {
+        let mut arg = None;
+        let mut coroutine = Mirror::new(&source, &copy)
+            .with_delete(true)
+            .with_batch_size(1);
+
+        let actions = loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(actions) => break actions,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(FsIo::ReadFiles(Err(paths))) => {
+                    assert_eq!(1, paths.len());
+                    arg = Some(handle(FsIo::ReadFiles(Err(paths))).unwrap());
+                }
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        };
+
+        if n == 0 {
+            assert_eq!(7, actions.len());
+        } else {
+            assert!(actions.is_empty(), "{actions:?}");
+        }
+    }
+
+    let modified = |path: &Path| std::fs::metadata(path).unwrap().modified().unwrap();
+    assert_eq!(
+        modified(&source.join("edited")),
+        modified(&copy.join("edited"))
+    );
+}
+
+#[cfg(any(feature = "blake3", feature = "sha2"))]
+#[test]
+fn hash_files() {
+    use io_fs::coroutines::hash_files::{HashAlgorithm, HashFiles};
+
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let empty = workdir.path().join("empty");
+    let hello = workdir.path().join("hello");
+    std::fs::write(&empty, b"").unwrap();
+    std::fs::write(&hello, b"hello").unwrap();
+
+    let mut expected = Vec::new();
+
+    #[cfg(feature = "blake3")]
+    expected.push((
+        HashAlgorithm::Blake3,
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        "ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f",
+    ));
+
+    #[cfg(feature = "sha2")]
+    expected.push((
+        HashAlgorithm::Sha256,
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+    ));
+
+    for (algorithm, empty_hex, hello_hex) in expected {
+        let mut arg = None;
+        let mut reads = 0;
+        let mut coroutine = HashFiles::new([&empty, &hello], algorithm).with_chunk_size(2);
+
+        let digests = loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(digests) => break digests,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => {
+                    reads += 1;
+                    arg = Some(handle(io).unwrap());
+                }
+            }
+        };
+
+        // "hello" is read by chunks of 2 bytes: "he", "ll", "o"
+        assert_eq!(3, reads);
+        assert_eq!(empty_hex, digests[&empty].to_hex());
+        assert_eq!(hello_hex, digests[&hello].to_hex());
+
+        // files are read one after the other
+        let mut arg = None;
+        let mut reads = 0;
+        let mut coroutine = HashFiles::new([&empty, &hello], algorithm)
+            .with_chunk_size(2)
+            .with_max_files(1);
+
+        let digests = loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(digests) => break digests,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(FsIo::ReadChunks(Err(chunks))) => {
+                    assert_eq!(1, chunks.len());
+                    reads += 1;
+                    arg = Some(handle(FsIo::ReadChunks(Err(chunks))).unwrap());
+                }
+                FsResult::Io(io) => panic!("unexpected {io:?}"),
+            }
+        };
+
+        assert_eq!(4, reads);
+        assert_eq!(empty_hex, digests[&empty].to_hex());
+        assert_eq!(hello_hex, digests[&hello].to_hex());
+
+        // files read up to their end, or abandoned, are not kept open
+        let mut coroutine = HashFiles::new([&hello], algorithm).with_chunk_size(5);
+        let FsResult::Io(io) = coroutine.resume(None) else {
+            panic!("expected read chunks I/O request");
+        };
+        handle(io).unwrap();
+
+        for n in 0..100 {
+            let path = workdir.path().join(format!("abandoned{n}"));
+            std::fs::write(&path, b"abandoned").unwrap();
+            let mut coroutine = HashFiles::new([&path], algorithm).with_chunk_size(2);
+            let FsResult::Io(io) = coroutine.resume(None) else {
+                panic!("expected read chunks I/O request");
+            };
+            handle(io).unwrap();
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let open: Vec<_> = std::fs::read_dir("/proc/self/fd")
+                .unwrap()
+                .filter_map(|fd| std::fs::read_link(fd.unwrap().path()).ok())
+                .filter(|path| path.starts_with(workdir.path()))
+                .collect();
+
+            assert!(open.len() <= 64);
+            assert!(!open.contains(&hello));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch() {
+    use std::time::Duration;
+
+    use io_fs::{
+        coroutines::{poll_events::PollEvents, unwatch::Unwatch, watch::Watch},
+        io::FsEvent,
+    };
+
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let file1 = workdir.path().join("file1");
+    let file2 = workdir.path().join("file2");
+    let dir = workdir.path().join("dir");
+    let file3 = dir.join("file3");
+
+    let mut arg = None;
+    let mut coroutine = Watch::new(workdir.path());
+
+    let id = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(id) => break id,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    let poll = || {
+        let mut arg = None;
+        let mut coroutine = PollEvents::new(id, Duration::from_secs(1));
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(events) => break events,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    std::fs::write(&file1, b"file1").unwrap();
+    std::fs::rename(&file1, &file2).unwrap();
+    std::fs::remove_file(&file2).unwrap();
+
+    let expected = vec![
+        FsEvent::Created(file1.clone()),
+        FsEvent::Modified(file1.clone()),
+        FsEvent::Renamed(file1.clone(), file2.clone()),
+        FsEvent::Removed(file2.clone()),
+    ];
+
+    assert_eq!(expected, poll());
+
+    // directories created after the watcher are watched as well
+
+    std::fs::create_dir(&dir).unwrap();
+    assert_eq!(vec![FsEvent::Created(dir.clone())], poll());
+
+    std::fs::write(&file3, b"").unwrap();
+    assert_eq!(vec![FsEvent::Created(file3.clone())], poll());
+
+    // entries of new directories are reported as well
+
+    let subdir = workdir.path().join("subdir");
+    let file4 = subdir.join("dir").join("file4");
+    std::fs::create_dir_all(file4.parent().unwrap()).unwrap();
+    std::fs::write(&file4, b"").unwrap();
+
+    let expected = vec![
+        FsEvent::Created(subdir.clone()),
+        FsEvent::Created(subdir.join("dir")),
+        FsEvent::Created(file4.clone()),
+    ];
+
+    assert_eq!(expected, poll());
+
+    // lost events are reported once the queue overflowed
+
+    let max = std::fs::read_to_string("/proc/sys/fs/inotify/max_queued_events").unwrap();
+    let max: usize = max.trim().parse().unwrap();
+
+    if max <= 100_000 {
+        for n in 0..=max {
+            std::fs::write(dir.join(format!("file{n}")), b"").unwrap();
+        }
+
+        assert!(poll().contains(&FsEvent::Overflowed));
+    }
+
+    let mut arg = None;
+    let mut coroutine = Unwatch::new(id);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    let err = handle(FsIo::Unwatch(Err(id))).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+}
+
+#[test]
+fn detect_changes() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    std::fs::create_dir(workdir.path().join("dir")).unwrap();
+    std::fs::write(workdir.path().join("dir/file1"), b"file1").unwrap();
+    std::fs::write(workdir.path().join("file2"), b"file2").unwrap();
+
+    let detect = |snapshot| {
+        let mut arg = None;
+        let mut coroutine = DetectChanges::new(snapshot);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(changes) => break changes,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    // everything is added since an empty snapshot
+
+    let (diff, snapshot) = detect(Snapshot::new(workdir.path()));
+
+    let added: BTreeSet<_> = ["dir", "dir/file1", "file2"]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(added, diff.added);
+    assert_eq!(3, snapshot.entries().len());
+
+    // snapshots survive process restarts
+
+    #[cfg(feature = "serde")]
+    let snapshot: Snapshot =
+        serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+
+    std::fs::write(workdir.path().join("dir/file1"), b"file1 edited").unwrap();
+    std::fs::remove_file(workdir.path().join("file2")).unwrap();
+    std::fs::write(workdir.path().join("file3"), b"file3").unwrap();
+
+    let (diff, snapshot) = detect(snapshot);
+
+    assert_eq!(BTreeSet::from_iter([PathBuf::from("file3")]), diff.added);
+    assert_eq!(BTreeSet::from_iter([PathBuf::from("file2")]), diff.removed);
+    assert_eq!(
+        BTreeSet::from_iter([PathBuf::from("dir/file1")]),
+        diff.modified
+    );
+
+    // nothing changed since the last snapshot
+
+    let (diff, _) = detect(snapshot);
+    assert!(diff.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn lock() {
+    use std::fs::{File, TryLockError};
+
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let path = workdir.path().join("file");
+    std::fs::write(&path, b"file").unwrap();
+
+    let run = |mut coroutine: Lock| {
+        let mut arg = None;
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(id) => break id,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    // shared locks do not conflict with each other
+
+    let id = run(Lock::new(&path, LockMode::Shared));
+    File::open(&path).unwrap().try_lock_shared().unwrap();
+    let other = run(Lock::new(&path, LockMode::TryShared));
+    assert_ne!(id, other);
+
+    // locks of the same runtime conflict with each other
+
+    let err = handle(FsIo::Lock(Err((path.clone(), LockMode::TryExclusive)))).unwrap_err();
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+
+    let err = File::open(&path).unwrap().try_lock().unwrap_err();
+    assert!(matches!(err, TryLockError::WouldBlock));
+
+    // other owners can lock once all locks are released
+
+    for id in [id, other] {
+        let mut arg = None;
+        let mut coroutine = Unlock::new(id);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(()) => break,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    }
+
+    File::open(&path).unwrap().try_lock().unwrap();
+
+    let err = handle(FsIo::Unlock(Err(id))).unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, err.kind());
+}
+
+#[test]
+fn lock_file() {
+    use std::time::{Duration, SystemTime};
+
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let path = workdir.path().join("file");
+
+    let acquire = |mut coroutine: LockFile| {
+        let mut arg = None;
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(()) => break Ok(()),
+                FsResult::Err(err) => break Err(err),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+            }
+        }
+    };
+
+    let lock = LockFile::new(&path);
+    assert_eq!(workdir.path().join("file.lock"), lock.path());
+
+    acquire(lock).unwrap();
+
+    let pid = std::fs::read_to_string(workdir.path().join("file.lock")).unwrap();
+    assert_eq!(format!("{}\n", std::process::id()), pid);
+
+    // the lock is held
+
+    let Err(FsError::Io(err)) = acquire(LockFile::new(&path)) else {
+        panic!("expected lock error");
+    };
+
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind);
+
+    // old locks of alive processes are not stale
+
+    let lock = LockFile::new(&path).with_stale_after(Duration::ZERO);
+    let Err(FsError::Io(err)) = acquire(lock) else {
+        panic!("expected lock error");
+    };
+
+    assert_eq!(io::ErrorKind::WouldBlock, err.kind);
+
+    // refreshing the lock updates its modification time
+
+    let lock_path = workdir.path().join("file.lock");
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(&lock_path)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+
+    let mut arg = None;
+    let mut coroutine = LockFile::new(&path).refresh();
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    let modified = std::fs::metadata(&lock_path).unwrap().modified().unwrap();
+    assert!(modified > old);
+
+    // stale locks of dead processes, or without process, are taken
+    // over
+
+    for contents in [format!("{}\n", u32::MAX), String::new()] {
+        std::fs::write(&lock_path, contents).unwrap();
+        acquire(LockFile::new(&path).with_stale_after(Duration::ZERO)).unwrap();
+
+        let pid = std::fs::read_to_string(&lock_path).unwrap();
+        assert_eq!(format!("{}\n", std::process::id()), pid);
+    }
+
+    let entries = std::fs::read_dir(workdir.path()).unwrap().count();
+    assert_eq!(1, entries);
+
+    // the lock is released by removing the lock file
+
+    let lock = LockFile::new(&path);
+    let mut arg = None;
+    let mut coroutine = lock.release();
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    acquire(lock).unwrap();
+}
+
+#[test]
+fn durable() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let tmp = workdir.path().join("tmp");
+    let new = workdir.path().join("new");
+    std::fs::create_dir(&tmp).unwrap();
+    std::fs::create_dir(&new).unwrap();
+
+    // data is flushed before its directory entry
+
+    let mut arg = None;
+    let mut requests = Vec::new();
+    let mut coroutine = CreateFile::new(tmp.join("msg"), *b"data").with_durable(true);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => {
+                requests.push(io.clone());
+                arg = Some(handle(io).unwrap());
+            }
+        }
+    }
+
+    let expected = vec![
+        FsIo::CreateFile(Err((tmp.join("msg"), b"data".to_vec()))),
+        FsIo::SyncFile(Err(tmp.join("msg"))),
+        FsIo::SyncDir(Err(tmp.clone())),
+    ];
+
+    assert_eq!(expected, requests);
+
+    // renames flush both source and destination directories
+
+    let mut arg = None;
+    let mut requests = Vec::new();
+    let mut coroutine = Rename::new([(tmp.join("msg"), new.join("msg"))]).with_durable(true);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => {
+                requests.push(io.clone());
+                arg = Some(handle(io).unwrap());
+            }
+        }
+    }
+
+    let expected = vec![
+        FsIo::Rename(Err((
+            vec![(tmp.join("msg"), new.join("msg"))],
+            RenameMode::Replace,
+        ))),
+        FsIo::SyncDir(Err(new.clone())),
+        FsIo::SyncDir(Err(tmp.clone())),
+    ];
+
+    assert_eq!(expected, requests);
+    assert_eq!(b"data", std::fs::read(new.join("msg")).unwrap().as_slice());
+
+    // the dry-run runtime does not flush anything
+
+    let mut arg = None;
+    let mut dry_run = DryRun::new();
+    let mut coroutine = CreateFiles::new([(workdir.path().join("a"), *b"a")]).with_durable(true);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(dry_run.handle(io, handle).unwrap()),
+        }
+    }
+
+    assert!(!workdir.path().join("a").exists());
+    assert_eq!(1, dry_run.plan().len());
+}
+
+#[test]
+fn maildir() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let root = workdir.path().join("INBOX");
+
+    let mut arg = None;
+    let mut coroutine = CreateMaildir::new(&root);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    for dir in ["cur", "new", "tmp"] {
+        assert!(root.join(dir).is_dir());
+    }
+
+    // deliver a message
+
+    let mut arg = None;
+    let mut coroutine =
+        DeliverMessage::new(&root, *b"Subject: test\r\n\r\nbody").with_hostname("host:1");
+
+    let path = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(path) => break path,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(root.join("new"), path.parent().unwrap());
+    assert!(path.to_str().unwrap().ends_with(".host\\0721"));
+    assert_eq!(
+        b"Subject: test\r\n\r\nbody",
+        std::fs::read(&path).unwrap().as_slice()
+    );
+    assert_eq!(0, std::fs::read_dir(root.join("tmp")).unwrap().count());
+
+    // flags of messages still in new cannot be set
+
+    let FsResult::Err(FsError::Io(err)) = SetFlags::new(&path, ['S']).resume(None) else {
+        panic!("expected invalid path error");
+    };
+
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind);
+
+    // move it to cur
+
+    let mut arg = None;
+    let mut coroutine = MoveToCur::new(&path);
+
+    let path = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(path) => break path,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    let name = path.file_name().unwrap().to_str().unwrap();
+    assert_eq!(root.join("cur"), path.parent().unwrap());
+    assert!(name.ends_with(":2,"));
+
+    // messages already in cur cannot be moved to cur
+
+    let FsResult::Err(FsError::Io(err)) = MoveToCur::new(&path).resume(None) else {
+        panic!("expected invalid path error");
+    };
+
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind);
+
+    // change its flags
+
+    let (id, flags) = maildir::split_flags(name);
+    assert!(flags.is_empty());
+
+    let mut arg = None;
+    let mut coroutine = SetFlags::new(&path, ['S', 'F', 'S']);
+
+    let path = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(path) => break path,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(root.join("cur").join(format!("{id}:2,FS")), path);
+    assert!(path.is_file());
+
+    // unchanged flags need no I/O
+
+    let mut coroutine = SetFlags::new(&path, ['F', 'S']);
+    let FsResult::Ok(same) = coroutine.resume(None) else {
+        panic!("expected no I/O");
+    };
+
+    assert_eq!(path, same);
+
+    // experimental info is kept when moving to cur, and cannot be
+    // changed by flags
+
+    let new = root.join("new").join("1.M1P1Q1.host:1,experimental");
+    std::fs::write(&new, b"").unwrap();
+
+    let mut arg = None;
+    let mut coroutine = MoveToCur::new(&new);
+
+    let path = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(path) => break path,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(root.join("cur").join("1.M1P1Q1.host:1,experimental"), path);
+
+    let FsResult::Err(FsError::Io(err)) = SetFlags::new(&path, ['S']).resume(None) else {
+        panic!("expected invalid path error");
+    };
+
+    assert_eq!(io::ErrorKind::InvalidInput, err.kind);
+}
+
+#[test]
+fn vdir() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let collection = workdir.path();
+
+    std::fs::write(collection.join(".hidden.vcf"), b"").unwrap();
+    std::fs::write(collection.join("event.ics"), b"").unwrap();
+    std::fs::create_dir(collection.join("dir.vcf")).unwrap();
+
+    // write an item atomically
+
+    let mut arg = None;
+    let path = collection.join("contact.vcf");
+    let mut coroutine = WriteItem::new(&path, *b"BEGIN:VCARD").with_durable(true);
+
+    let etag = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(etag) => break etag,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(b"BEGIN:VCARD", std::fs::read(&path).unwrap().as_slice());
+
+    // list items by extension
+
+    let list = || {
+        let mut arg = None;
+        let mut coroutine = ListItems::new(collection, "vcf");
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(items) => break items,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    let expected = BTreeMap::from_iter([(path.clone(), etag.clone())]);
+    assert_eq!(expected, list());
+
+    // rewriting an item changes its etag
+
+    let mut arg = None;
+    let mut coroutine = WriteItem::new(&path, *b"BEGIN:VCARD\r\n");
+
+    let new_etag = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(etag) => break etag,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_ne!(etag, new_etag);
+    assert_eq!(new_etag, list()[&path]);
+
+    // concurrent writers do not share their temporary file
+
+    let mut first = WriteItem::new(&path, *b"first");
+    let mut second = WriteItem::new(&path, *b"second");
+
+    let FsResult::Io(FsIo::CreateNewFile(Err((first_tmp, contents)))) = first.resume(None) else {
+        panic!("expected create new file request");
+    };
+
+    let FsResult::Io(io) = second.resume(None) else {
+        panic!("expected I/O request");
+    };
+
+    assert!(matches!(&io, FsIo::CreateNewFile(Err((tmp, _))) if *tmp != first_tmp));
+
+    let run = |coroutine: &mut WriteItem, io: FsIo| {
+        let mut arg = Some(handle(io).unwrap());
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(etag) => break etag,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    let second_etag = run(&mut second, io);
+    let first_etag = run(&mut first, FsIo::CreateNewFile(Err((first_tmp, contents))));
+
+    assert_eq!(b"first", std::fs::read(&path).unwrap().as_slice());
+    assert_ne!(first_etag, second_etag);
+    assert_eq!(first_etag, list()[&path]);
+
+    // temporary files are removed when writing fails
+
+    let mut arg = None;
+    let mut coroutine = WriteItem::new(collection.join("dir.vcf"), *b"BEGIN:VCARD");
+
+    let err = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(etag) => panic!("unexpected etag {etag}"),
+            FsResult::Err(err) => break err,
+            FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+        }
+    };
+
+    assert!(matches!(err, FsError::Io(_)));
+
+    let tmps = std::fs::read_dir(collection)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().ends_with(".tmp"))
+        .count();
+
+    assert_eq!(0, tmps);
+
+    // read and write collection metadata
+
+    let read_meta = || {
+        let mut arg = None;
+        let mut coroutine = ReadMeta::new(collection);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(meta) => break meta,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    assert_eq!(CollectionMeta::default(), read_meta());
+
+    let meta = CollectionMeta {
+        displayname: Some("Contacts".into()),
+        color: Some("#ff0000".into()),
+    };
+
+    let mut arg = None;
+    let mut coroutine = WriteMeta::new(collection, &meta);
+
+    loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(()) => break,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    }
+
+    let displayname = std::fs::read_to_string(collection.join("displayname")).unwrap();
+    assert_eq!("Contacts\n", displayname);
+    assert_eq!(meta, read_meta());
+}
+
+#[test]
+fn mbox() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let path = workdir.path().join("archive.mbox");
+
+    let messages = [
+        MboxMessage::new(*b"Subject: first\n\nFrom here\n>From there\n"),
+        MboxMessage::new(*b"Subject: second\n\nbody")
+            .with_from_line("alice@localhost Thu Jan  1 00:00:00 1970"),
+    ];
+
+    let append = |messages: &[MboxMessage]| {
+        let mut arg = None;
+        let mut coroutine = AppendMbox::new(&path, messages);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(()) => break,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+            }
+        }
+    };
+
+    // messages are appended one after the other
+
+    for message in &messages {
+        append(std::slice::from_ref(message));
+    }
+
+    let mbox = std::fs::read_to_string(&path).unwrap();
+    assert!(mbox.starts_with("From MAILER-DAEMON "));
+    assert!(mbox.contains("\n>From here\n>>From there\n\nFrom alice@localhost "));
+    assert!(mbox.ends_with("\n\nbody\n\n"));
+
+    // quoted lines are unquoted when read
+
+    let mut arg = None;
+    let mut coroutine = ReadMbox::new(&path);
+
+    let read = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(messages) => break messages,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+        }
+    };
+
+    assert_eq!(2, read.len());
+    assert_eq!(messages[0], read[0]);
+    assert_eq!(messages[1].from_line, read[1].from_line);
+    assert_eq!(b"Subject: second\n\nbody\n", read[1].contents.as_slice());
+
+    // a line ending is added to files not ending with one
+
+    std::fs::write(&path, b"From MAILER-DAEMON\n\nbody").unwrap();
+    append(&messages[1..]);
+
+    let mbox = std::fs::read_to_string(&path).unwrap();
+    assert!(mbox.starts_with("From MAILER-DAEMON\n\nbody\nFrom alice@localhost "));
+
+    // the last byte is read from the file a symbolic link points to
+
+    #[cfg(unix)]
+    {
+        let link = workdir.path().join("link.mbox");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+
+        // the byte before the length of the link is a line ending
+        let len = path.as_os_str().len();
+        let mut contents = b"From MAILER-DAEMON".to_vec();
+        contents.extend(vec![b'\n'; len]);
+        contents.extend_from_slice(b"body");
+        std::fs::write(&path, &contents).unwrap();
+
+        let mut arg = None;
+        let mut coroutine = AppendMbox::new(&link, &messages[1..]);
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(()) => break,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+            }
+        }
+
+        let mbox = std::fs::read_to_string(&path).unwrap();
+        assert!(mbox.contains("\n\nbody\nFrom alice@localhost "));
+    }
+}
+
+#[test]
+fn glob() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let root = workdir.path();
+
+    for dir in ["contacts/work/old", "contacts/.git", "notes"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    for file in [
+        "contacts/a.vcf",
+        "contacts/b.vcf",
+        "contacts/x.ics",
+        "contacts/.hidden.vcf",
+        "contacts/.git/c.vcf",
+        "contacts/work/c.vcf",
+        "contacts/work/old/d.vcf",
+        "notes/e.vcf",
+        "f.vcf",
+    ] {
+        std::fs::write(root.join(file), b"").unwrap();
+    }
+
+    let glob = |coroutine: Glob| {
+        let mut arg = None;
+        let mut coroutine = coroutine;
+        let mut read_dirs = BTreeSet::new();
+
+        let paths = loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(paths) => break paths,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => {
+                    if let FsIo::ReadDir(Err(dir)) = &io {
+                        let dir = dir.strip_prefix(root).unwrap().to_path_buf();
+                        assert!(read_dirs.insert(dir), "directory read twice");
+                    }
+
+                    arg = Some(handle(io).unwrap_or_else(FsIo::from));
+                }
+            }
+        };
+
+        let paths: Vec<_> = paths
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+
+        (paths, read_dirs)
+    };
+
+    // recursive patterns only read the needed subtree, hidden
+    // entries are skipped
+
+    let (paths, read_dirs) = glob(Glob::new(root, "contacts/**/*.vcf"));
+    let expected = [
+        "contacts/a.vcf",
+        "contacts/b.vcf",
+        "contacts/work/c.vcf",
+        "contacts/work/old/d.vcf",
+    ];
+
+    assert_eq!(expected.to_vec(), paths);
+    assert!(!read_dirs.contains(&PathBuf::new()));
+    assert!(!read_dirs.contains(&PathBuf::from("notes")));
+
+    // character classes, brace expansion and explicit hidden names
+
+    let (paths, read_dirs) = glob(Glob::new(root, "{contacts,notes}/[!b].{vcf,ics}"));
+    let expected = ["contacts/a.vcf", "contacts/x.ics", "notes/e.vcf"];
+
+    assert_eq!(expected.to_vec(), paths);
+    assert_eq!(2, read_dirs.len());
+
+    let (paths, _) = glob(Glob::new(root, "contacts/.*.vcf").with_pattern("f.vcf"));
+    assert_eq!(vec!["contacts/.hidden.vcf", "f.vcf"], paths);
+
+    let (paths, _) = glob(Glob::new(root, "**/c.vcf").with_hidden(true));
+    assert_eq!(vec!["contacts/.git/c.vcf", "contacts/work/c.vcf"], paths);
+
+    // missing entries do not match
+
+    let (paths, _) = glob(Glob::new(root, "missing/**/*.vcf").with_pattern("f.vcf/*"));
+    assert!(paths.is_empty());
+}
+
+#[test]
+fn ignore() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let root = workdir.path();
+
+    for dir in ["contacts/.git", "contacts/backup", "contacts/work"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    let files = [
+        (".gitignore", "# editor files\n*.swp\n!keep.swp\nbackup/\n"),
+        ("contacts/.gitignore", "*~\n/local.vcf\n"),
+        ("contacts/.git/config", ""),
+        ("contacts/a.vcf", ""),
+        ("contacts/a.vcf~", ""),
+        ("contacts/.a.vcf.swp", ""),
+        ("contacts/keep.swp", ""),
+        ("contacts/local.vcf", ""),
+        ("contacts/backup/b.vcf", ""),
+        ("contacts/work/local.vcf", ""),
+    ];
+
+    for (file, contents) in files {
+        std::fs::write(root.join(file), contents).unwrap();
+    }
+
+    let relative = |paths: BTreeSet<PathBuf>| -> Vec<String> {
+        paths
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect()
+    };
+
+    // walks apply nested ignore files and extra rules, without
+    // reading ignored directories
+
+    let rules = IgnoreRules::new().with_rules(root, ".git/");
+    let mut arg = None;
+    let mut coroutine = Walk::new(root)
+        .with_ignore_file(GITIGNORE)
+        .with_ignore_rules(rules);
+
+    let entries = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(entries) => break entries,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => {
+                if let FsIo::ReadDir(Err(dir)) = &io {
+                    assert!(!dir.ends_with("backup") && !dir.ends_with(".git"));
+                }
+
+                arg = Some(handle(io).unwrap());
+            }
+        }
+    };
+
+    let expected = [
+        ".gitignore",
+        "contacts",
+        "contacts/.gitignore",
+        "contacts/a.vcf",
+        "contacts/keep.swp",
+        "contacts/work",
+        "contacts/work/local.vcf",
+    ];
+
+    assert_eq!(expected.to_vec(), relative(entries.into_keys().collect()));
+
+    // globs read ignore files of parent directories as well
+
+    let mut arg = None;
+    let mut coroutine = Glob::new(root, "contacts/**/*")
+        .with_hidden(true)
+        .with_ignore_file(GITIGNORE)
+        .with_ignore_rules(IgnoreRules::new().with_rules(root, ".git/"));
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(handle(io).unwrap_or_else(FsIo::from)),
+        }
+    };
+
+    let expected = [
+        "contacts/.gitignore",
+        "contacts/a.vcf",
+        "contacts/keep.swp",
+        "contacts/work",
+        "contacts/work/local.vcf",
+    ];
+
+    assert_eq!(expected.to_vec(), relative(paths));
+}
+
+#[test]
+fn disk_usage() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let root = workdir.path();
+
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::create_dir(root.join("c")).unwrap();
+    std::fs::write(root.join("file"), [0; 10]).unwrap();
+    std::fs::write(root.join("a/file"), [0; 20]).unwrap();
+    std::fs::write(root.join("a/b/file"), [0; 30]).unwrap();
+    std::fs::hard_link(root.join("a/b/file"), root.join("c/link")).unwrap();
+
+    let disk_usage = |coroutine: DiskUsage| {
+        let mut arg = None;
+        let mut coroutine = coroutine;
+
+        loop {
+            match coroutine.resume(arg) {
+                FsResult::Ok(usages) => break usages,
+                FsResult::Err(err) => panic!("{err}"),
+                FsResult::Io(io) => arg = Some(handle(io).unwrap()),
+            }
+        }
+    };
+
+    let usages = disk_usage(DiskUsage::new(root));
+
+    let usage = |size, files, dirs| Usage { size, files, dirs };
+    let expected = BTreeMap::from_iter([
+        (root.to_path_buf(), usage(90, 4, 3)),
+        (root.join("a"), usage(50, 2, 1)),
+        (root.join("a/b"), usage(30, 1, 0)),
+        (root.join("c"), usage(30, 1, 0)),
+    ]);
+
+    assert_eq!(expected, usages);
+
+    // hard links are counted once, at their first path
+
+    #[cfg(unix)]
+    {
+        let usages = disk_usage(DiskUsage::new(root).with_hard_links_once(true));
+
+        assert_eq!(usage(60, 3, 3), usages[root]);
+        assert_eq!(usage(30, 1, 0), usages[&root.join("a/b")]);
+        assert_eq!(usage(0, 0, 0), usages[&root.join("c")]);
+    }
+}
@@ -1,6 +1,11 @@
 #![cfg(feature = "tokio")]
+#![allow(clippy::bool_assert_comparison)]
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    time::Duration,
+};
 
 use io_fs::{
     coroutines::{
@@ -10,9 +15,11 @@ use io_fs::{
         remove_files::RemoveFiles, rename::Rename,
     },
     error::FsResult,
-    runtimes::tokio::handle,
+    io::{FsIo, LockMode, RenameMode},
+    runtimes::{read_only::ReadOnly, tokio::handle},
 };
 use tempfile::tempdir;
+use tokio::time;
 
 #[tokio::test]
 async fn tokio() {
@@ -98,7 +105,7 @@ async fn tokio() {
         }
     };
 
-    let expected_paths = HashSet::from_iter([workdir.path().join("dir1").join("file1")]);
+    let expected_paths = BTreeSet::from_iter([workdir.path().join("dir1").join("file1")]);
 
     assert_eq!(paths, expected_paths);
 
@@ -113,7 +120,7 @@ async fn tokio() {
         }
     };
 
-    let expected_paths = HashSet::from_iter([
+    let expected_paths = BTreeSet::from_iter([
         workdir.path().join("dir2").join("file2"),
         workdir.path().join("dir2").join("file3"),
     ]);
@@ -164,7 +171,7 @@ async fn tokio() {
         }
     };
 
-    let expected_contents = HashMap::from_iter([
+    let expected_contents = BTreeMap::from_iter([
         (workdir.path().join("dir2").join("file2"), b"file2".to_vec()),
         (workdir.path().join("dir2").join("file3"), b"file3".to_vec()),
     ]);
@@ -255,3 +262,104 @@ async fn tokio() {
     assert_eq!(false, workdir.path().join("dir1").is_dir());
     assert_eq!(false, workdir.path().join("dir2").is_dir());
 }
+
+#[tokio::test]
+async fn rename_no_replace() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let file1 = workdir.path().join("file1");
+    let file2 = workdir.path().join("file2");
+    std::fs::write(&file1, b"file1").unwrap();
+    std::fs::write(&file2, b"file2").unwrap();
+
+    let mut coroutine = Rename::new([(&file1, &file2)]).with_mode(RenameMode::NoReplace);
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected rename I/O request");
+    };
+
+    let err = handle(io).await.unwrap_err();
+
+    assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+    assert_eq!(b"file2", std::fs::read(&file2).unwrap().as_slice());
+}
+
+#[tokio::test]
+async fn read_only() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let mut runtime = ReadOnly::new(handle);
+
+    // read requests pass through
+
+    let mut arg = None;
+    let mut coroutine = ReadDir::new(workdir.path());
+
+    let paths = loop {
+        match coroutine.resume(arg) {
+            FsResult::Ok(paths) => break paths,
+            FsResult::Err(err) => panic!("{err}"),
+            FsResult::Io(io) => arg = Some(runtime.handle_async(io).await.unwrap()),
+        }
+    };
+
+    assert!(paths.is_empty());
+
+    // mutating requests are denied
+
+    let mut coroutine = CreateDir::new(workdir.path().join("dir1"));
+
+    let FsResult::Io(io) = coroutine.resume(None) else {
+        panic!("expected create dir I/O request");
+    };
+
+    let err = runtime.handle_async(io).await.unwrap_err();
+
+    assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+    assert!(!workdir.path().join("dir1").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn lock() {
+    let _ = env_logger::try_init();
+
+    let workdir = tempdir().unwrap();
+    let path = workdir.path().join("file");
+    std::fs::write(&path, b"").unwrap();
+
+    let lock = |mode| handle(FsIo::Lock(Err((path.clone(), mode))));
+
+    let FsIo::Lock(Ok(id)) = lock(LockMode::Exclusive).await.unwrap() else {
+        panic!("expected lock output");
+    };
+
+    // waiting for the lock is abandoned
+
+    let wait = lock(LockMode::Exclusive);
+    assert!(time::timeout(Duration::from_millis(50), wait)
+        .await
+        .is_err());
+
+    handle(FsIo::Unlock(Err(id))).await.unwrap();
+
+    // the lock acquired by the abandoned request is released
+
+    let mut attempts = 0;
+
+    loop {
+        match lock(LockMode::TryExclusive).await {
+            Ok(FsIo::Lock(Ok(id))) => {
+                handle(FsIo::Unlock(Err(id))).await.unwrap();
+                break;
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock && attempts < 100 => {
+                attempts += 1;
+                time::sleep(Duration::from_millis(10)).await;
+            }
+            res => panic!("unexpected {res:?}"),
+        }
+    }
+}